@@ -0,0 +1,41 @@
+//! Helpers shared by the benchmarks.
+
+use std::sync::Barrier;
+use std::thread;
+use std::time::{Duration, Instant};
+
+/// The number of times each thread runs the benchmarked operation.
+pub const ITERATIONS: u32 = 10_000_000;
+
+/// Get the thread count passed on the command line, defaulting to the number of available CPUs.
+pub fn threads() -> usize {
+    // Cargo passes `--bench` to benchmarks without a harness, so skip any flags.
+    std::env::args()
+        .skip(1)
+        .find(|arg| !arg.starts_with('-'))
+        .map(|arg| arg.parse().expect("thread count must be a number"))
+        .unwrap_or_else(|| thread::available_parallelism().map_or(1, usize::from))
+}
+
+/// Run `f` [`ITERATIONS`] times on each of `threads` threads at once, returning the time taken.
+pub fn run(threads: usize, f: impl Fn() + Sync) -> Duration {
+    let barrier = Barrier::new(threads);
+    let start = Instant::now();
+    thread::scope(|s| {
+        for _ in 0..threads {
+            s.spawn(|| {
+                barrier.wait();
+                for _ in 0..ITERATIONS {
+                    f();
+                }
+            });
+        }
+    });
+    start.elapsed()
+}
+
+/// Print the time each operation took on each thread.
+pub fn report(name: &str, threads: usize, elapsed: Duration) {
+    let per_op = elapsed.as_secs_f64() * 1e9 / f64::from(ITERATIONS);
+    println!("{name:<36} {threads:>3} threads: {per_op:>7.2}ns per operation per thread");
+}
@@ -0,0 +1,82 @@
+//! Compares `TryRwLock` against `std::sync::RwLock`'s non-blocking methods.
+//!
+//! Measures uncontended `try_read` and `try_write`, `try_read` with every thread reading the same
+//! lock, `try_write` with every thread failing on a read-locked lock, and upgrading a reader to a
+//! writer and downgrading it back.
+//!
+//! Run with `cargo bench --bench compare`. Pass a thread count to change the amount of contention
+//! in the contended benchmarks (the default is the number of available CPUs), for example
+//! `cargo bench --bench compare -- 8`.
+
+mod common;
+
+use common::{report, run};
+use std::hint::black_box;
+use std::sync::RwLock;
+use try_rwlock::{ReadGuard, TryRwLock, WriteGuard};
+
+fn main() {
+    let threads = common::threads();
+
+    let lock = TryRwLock::new(0_u64);
+    let std_lock = RwLock::new(0_u64);
+
+    report(
+        "TryRwLock::try_read",
+        1,
+        run(1, || drop(black_box(black_box(&lock).try_read()))),
+    );
+    report(
+        "RwLock::try_read",
+        1,
+        run(1, || drop(black_box(black_box(&std_lock).try_read()))),
+    );
+    report(
+        "TryRwLock::try_write",
+        1,
+        run(1, || drop(black_box(black_box(&lock).try_write()))),
+    );
+    report(
+        "RwLock::try_write",
+        1,
+        run(1, || drop(black_box(black_box(&std_lock).try_write()))),
+    );
+
+    report(
+        "TryRwLock::try_read (contended)",
+        threads,
+        run(threads, || drop(black_box(black_box(&lock).try_read()))),
+    );
+    report(
+        "RwLock::try_read (contended)",
+        threads,
+        run(threads, || drop(black_box(black_box(&std_lock).try_read()))),
+    );
+
+    let reader = lock.try_read().unwrap();
+    report(
+        "TryRwLock::try_write (read-locked)",
+        threads,
+        run(threads, || drop(black_box(black_box(&lock).try_write()))),
+    );
+    drop(reader);
+    let reader = std_lock.try_read().unwrap();
+    report(
+        "RwLock::try_write (read-locked)",
+        threads,
+        run(threads, || {
+            drop(black_box(black_box(&std_lock).try_write()))
+        }),
+    );
+    drop(reader);
+
+    report(
+        "try_upgrade and downgrade",
+        1,
+        run(1, || {
+            let reader = black_box(&lock).try_read().unwrap();
+            let writer = ReadGuard::try_upgrade(reader).ok().unwrap();
+            drop(black_box(WriteGuard::downgrade(writer)));
+        }),
+    );
+}
@@ -0,0 +1,72 @@
+//! Compares a compare-exchange loop against an unconditional `fetch_add` that is rolled back when
+//! a writer is present, the two ways of implementing `TryRwLock::try_read`.
+//!
+//! Run with `cargo bench --bench try_read`. Pass a thread count to change the amount of
+//! contention (the default is the number of available CPUs), for example
+//! `cargo bench --bench try_read -- 8`.
+
+mod common;
+
+use common::{report, run};
+use std::hint::black_box;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use try_rwlock::TryRwLock;
+
+const WRITER: usize = !(usize::MAX >> 1);
+
+/// Acquire and immediately release a read lock using a compare-exchange loop.
+fn compare_exchange_read(state: &AtomicUsize) -> bool {
+    let mut current = state.load(Ordering::Relaxed);
+    loop {
+        if current & WRITER != 0 {
+            return false;
+        }
+        match state.compare_exchange_weak(
+            current,
+            current + 1,
+            Ordering::Acquire,
+            Ordering::Relaxed,
+        ) {
+            Ok(_) => break,
+            Err(actual) => current = actual,
+        }
+    }
+    state.fetch_sub(1, Ordering::Release);
+    true
+}
+
+/// Acquire and immediately release a read lock using `fetch_add`, backing out if a writer is
+/// found.
+fn fetch_add_read(state: &AtomicUsize) -> bool {
+    let previous = state.fetch_add(1, Ordering::Acquire);
+    if previous & WRITER != 0 {
+        state.fetch_sub(1, Ordering::Relaxed);
+        return false;
+    }
+    state.fetch_sub(1, Ordering::Release);
+    true
+}
+
+fn main() {
+    let threads = common::threads();
+
+    for threads in [1, threads] {
+        let state = AtomicUsize::new(0);
+        let elapsed = run(threads, || {
+            black_box(compare_exchange_read(black_box(&state)));
+        });
+        report("compare_exchange loop", threads, elapsed);
+
+        let state = AtomicUsize::new(0);
+        let elapsed = run(threads, || {
+            black_box(fetch_add_read(black_box(&state)));
+        });
+        report("fetch_add with rollback", threads, elapsed);
+
+        let lock = TryRwLock::new(0_u64);
+        let elapsed = run(threads, || {
+            black_box(black_box(&lock).try_read());
+        });
+        report("TryRwLock::try_read", threads, elapsed);
+    }
+}
@@ -0,0 +1,72 @@
+//! Exponential backoff for loops that retry an operation on the lock.
+
+use ::core::hint;
+
+/// The number of steps for which backing off only spins, doubling the spin count each time.
+const SPIN_LIMIT: u32 = 6;
+
+/// Exponential backoff, escalating from spin loop hints to yielding to the OS scheduler.
+pub(crate) struct Backoff {
+    step: u32,
+}
+
+impl Backoff {
+    pub(crate) const fn new() -> Self {
+        Self { step: 0 }
+    }
+
+    /// Back off after contention on an atomic, such as a failed compare-exchange.
+    ///
+    /// This only ever spins, as the other thread is expected to make progress very soon.
+    pub(crate) fn spin(&mut self) {
+        for _ in 0..1_u32 << self.step.min(SPIN_LIMIT) {
+            hint::spin_loop();
+        }
+        if self.step <= SPIN_LIMIT {
+            self.step += 1;
+        }
+    }
+
+    /// Back off while waiting for another thread to release the lock.
+    ///
+    /// This spins at first, then yields to the OS scheduler with the `std` feature, or keeps
+    /// spinning without it.
+    #[cfg_attr(not(any(test, feature = "std")), allow(dead_code))]
+    pub(crate) fn snooze(&mut self) {
+        if self.step < SPIN_LIMIT {
+            for _ in 0..1_u32 << self.step {
+                hint::spin_loop();
+            }
+        } else {
+            #[cfg(feature = "std")]
+            std::thread::yield_now();
+            #[cfg(not(feature = "std"))]
+            for _ in 0..1_u32 << SPIN_LIMIT {
+                hint::spin_loop();
+            }
+        }
+        if self.step <= SPIN_LIMIT {
+            self.step += 1;
+        }
+    }
+}
+
+#[test]
+fn test_backoff() {
+    // The step stops growing once the longest spin is reached, so it can never overflow.
+    let mut backoff = Backoff::new();
+    for _ in 0..100 {
+        backoff.spin();
+    }
+    assert_eq!(backoff.step, SPIN_LIMIT + 1);
+
+    let mut backoff = Backoff::new();
+    for _ in 0..SPIN_LIMIT {
+        backoff.snooze();
+        assert!(backoff.step <= SPIN_LIMIT);
+    }
+    for _ in 0..100 {
+        backoff.snooze();
+    }
+    assert_eq!(backoff.step, SPIN_LIMIT + 1);
+}
@@ -3,6 +3,28 @@
 //!
 //! A readers-writer lock allows multiple readers or one writer to access it at a time.
 //!
+//! # Features
+//!
+//! This crate is `no_std` and does not allocate by default. Enabling the `alloc` feature adds APIs
+//! that require an allocator, such as the `Arc`-based `TryRwLock::try_read_owned` and
+//! `TryRwLock::try_write_owned`. The `std` feature implies `alloc` and additionally adds blocking
+//! `TryRwLock::read` and `TryRwLock::write` methods, which spin and yield to the OS scheduler until
+//! they acquire the lock.
+//!
+//! Enabling the `async` feature, which implies `alloc`, adds asynchronous locking with
+//! `TryRwLock::poll_read`, `TryRwLock::poll_write`, `TryRwLock::read_async` and
+//! `TryRwLock::write_async`. Every lock then keeps lists of the tasks waiting for it, which makes
+//! it 64 bytes larger on 64-bit targets, and each unlock that could let a waiting task in issues
+//! a sequentially consistent fence.
+//!
+//! Enabling the `cache-padded` feature aligns each lock's state to the target's cache line size, so
+//! that heavily used locks placed next to each other (for example in an array) do not contend on
+//! the same cache line. This makes every lock considerably larger.
+//!
+//! Enabling the `stats` feature records the largest number of readers that have held each lock at
+//! once, available from `TryRwLock::peak_readers`. This adds a counter to every lock and a little
+//! work to each read lock.
+//!
 //! # See Also
 //!
 //! [`try-lock`](https://crates.io/crates/try-lock) and
@@ -16,377 +38,3257 @@
 )]
 #![cfg_attr(not(test), no_std)]
 
+#[cfg(feature = "alloc")]
+extern crate alloc;
+#[cfg(feature = "std")]
+extern crate std;
+
+mod backoff;
+use backoff::Backoff;
+
+#[cfg(feature = "alloc")]
+mod owned;
+#[cfg(feature = "alloc")]
+pub use owned::{OwnedReadGuard, OwnedWriteGuard};
+#[cfg(feature = "async")]
+mod wakers;
+#[cfg(feature = "async")]
+pub use wakers::{ReadFuture, WriteFuture};
+
+mod multi;
+pub use multi::{try_read2, try_write2};
+#[cfg(feature = "alloc")]
+pub use multi::{try_read_all, try_write_all};
+
 use ::core::{
+    borrow::{Borrow, BorrowMut},
     cell::UnsafeCell,
     fmt::{self, Debug, Display, Formatter},
+    hash::{Hash, Hasher},
     marker::PhantomData,
-    mem::ManuallyDrop,
-    ops::{Deref, DerefMut},
+    mem::{self, ManuallyDrop},
+    ops::{AddAssign, Deref, DerefMut, SubAssign},
+    panic::{RefUnwindSafe, UnwindSafe},
     ptr::NonNull,
     sync::atomic::{self, AtomicUsize},
 };
 
+/// The bit of the lock's state that is set while a writer holds the lock.
+const WRITER: usize = !(usize::MAX >> 1);
+
+/// The bit of the lock's state that is set while an upgradable reader holds the lock.
+///
+/// While a writer holds the lock there can be no upgradable reader, so the bit is instead set
+/// while the write lock is split between two [`SplitWriteGuard`]s.
+const UPGRADABLE: usize = WRITER >> 1;
+
+/// The bits of the lock's state that count the readers holding the lock, including any
+/// upgradable reader.
+const READERS: usize = !(WRITER | UPGRADABLE);
+
+/// The default maximum number of readers, which is also the largest maximum a lock can have.
+///
+/// Readers optimistically increment the state before checking it, and back out if they find a
+/// writer or too many readers. Half of the reader bits are left as headroom for those in-flight
+/// increments so they can never overflow into the flag bits.
+const DEFAULT_MAX_READERS: usize = READERS >> 1;
+
+/// A value that, with the `cache-padded` feature, is aligned to the length of a cache line.
+///
+/// Most targets have 64-byte cache lines. On `x86_64` the spatial prefetcher fetches cache lines in
+/// pairs and recent `aarch64` cores (such as Apple's) have 128-byte lines, so those use 128, as
+/// does `powerpc64`. `s390x` has 256-byte cache lines.
+#[derive(Default)]
+#[repr(C)]
+#[cfg_attr(
+    all(
+        feature = "cache-padded",
+        any(
+            target_arch = "x86_64",
+            target_arch = "aarch64",
+            target_arch = "powerpc64",
+        ),
+    ),
+    repr(align(128))
+)]
+#[cfg_attr(all(feature = "cache-padded", target_arch = "s390x"), repr(align(256)))]
+#[cfg_attr(
+    all(
+        feature = "cache-padded",
+        not(any(
+            target_arch = "x86_64",
+            target_arch = "aarch64",
+            target_arch = "powerpc64",
+            target_arch = "s390x",
+        )),
+    ),
+    repr(align(64))
+)]
+struct CachePadded<T>(T);
+
+impl<T> Deref for CachePadded<T> {
+    type Target = T;
+
+    fn deref(&self) -> &Self::Target {
+        &self.0
+    }
+}
+
 /// A readers-writer lock.
+///
+/// The data may be unsized, so a `Box<TryRwLock<T>>` can be coerced to a
+/// `Box<TryRwLock<dyn Trait>>` or a `&TryRwLock<[T; N]>` to a `&TryRwLock<[T]>`.
+///
+/// `MAX_READERS` is the number of readers the lock allows at once. Once it is reached, further
+/// attempts to read-lock fail until a reader is dropped, so a small limit lets the lock double as
+/// a semaphore with an exclusive mode. It defaults to `usize::MAX >> 3`, which is also the largest
+/// allowed limit; a larger limit is a compile-time error when the lock is used.
+///
+/// # Layout
+///
+/// `TryRwLock` is `#[repr(C)]`. Its first field is the lock state, an [`AtomicUsize`] at offset 0
+/// that is 0 when the lock is unlocked, and its last field is the data.
+///
+/// Only with the `async`, `cache-padded` and `stats` features all disabled is the layout that of
+/// `#[repr(C)] struct { state: usize, data: T }`, and only then can a lock be set up in memory
+/// shared with other code by zeroing the state and writing the data after it. The `alloc` feature
+/// on its own does not change the layout.
+///
+/// With any of those features enabled, this does not hold. `cache-padded` aligns the state to a
+/// cache line whose size depends on the target, and `async` and `stats` add private fields
+/// between the state and the data, so the offset of the data cannot be known from outside Rust.
+/// Those fields are not valid when zeroed either (the waiter lists of `async` hold `Vec`s), so
+/// setting up a lock from raw bytes is undefined behavior; create it with [`new`](Self::new)
+/// instead.
 #[derive(Default)]
-pub struct TryRwLock<T> {
-    /// The number of readers currently holding the lock. 0 means the lock is free, usize::MAX
-    /// means there are usize::MAX readers or it is being written.
-    readers: AtomicUsize,
+#[repr(C)]
+pub struct TryRwLock<T: ?Sized, const MAX_READERS: usize = DEFAULT_MAX_READERS> {
+    /// The state of the lock. The [`WRITER`] bit is set if it is being written, otherwise the
+    /// [`READERS`] bits hold the number of readers and the [`UPGRADABLE`] bit is set if one of
+    /// those readers is upgradable. 0 means the lock is free. The reader bits may additionally
+    /// include readers that failed to lock and are about to back out, and the [`UPGRADABLE`] bit
+    /// is also set while a write lock is split.
+    state: CachePadded<AtomicUsize>,
+    /// Tasks waiting for the lock to be released.
+    #[cfg(feature = "async")]
+    wakers: wakers::Waiters,
+    /// The largest number of readers that have held the lock at once.
+    #[cfg(feature = "stats")]
+    peak_readers: AtomicUsize,
     /// The internal value.
     data: UnsafeCell<T>,
 }
 
 impl<T> TryRwLock<T> {
-    /// Create a new unlocked `TryRwLock<T>`.
+    /// Create a new unlocked `TryRwLock<T>` with the default maximum number of readers.
+    ///
+    /// This is a `const fn`, so unlike [`Default::default`] it can be used to initialize a
+    /// `static`:
+    ///
+    /// ```
+    /// use try_rwlock::TryRwLock;
+    ///
+    /// static NAMES: TryRwLock<Vec<&str>> = TryRwLock::new(Vec::new());
+    ///
+    /// NAMES.try_write().unwrap().push("Ferris");
+    /// assert_eq!(*NAMES.try_read().unwrap(), ["Ferris"]);
+    /// ```
+    ///
+    /// `TryRwLock` is not `Copy`, so an array of locks can't be written as
+    /// `[TryRwLock::new(0); N]`. Use an inline `const` block to repeat the expression instead:
+    ///
+    /// ```
+    /// use try_rwlock::TryRwLock;
+    ///
+    /// static COUNTERS: [TryRwLock<u32>; 64] = [const { TryRwLock::new(0) }; 64];
+    ///
+    /// *COUNTERS[3].try_write().unwrap() += 1;
+    /// assert_eq!(*COUNTERS[3].try_read().unwrap(), 1);
+    /// ```
     #[must_use]
     pub const fn new(data: T) -> Self {
+        Self::with_max_readers(data)
+    }
+
+    /// Create a new `TryRwLock<T>` that starts out write-locked, without a guard.
+    ///
+    /// This lets data be built up behind the lock, for example in a `static`, and then published
+    /// with [`force_unlock`](Self::force_unlock). Until then every attempt to lock it fails, and
+    /// the creator is responsible for the write lock as if it held a [`WriteGuard`].
+    ///
+    /// ```
+    /// use try_rwlock::TryRwLock;
+    ///
+    /// static CONFIG: TryRwLock<Vec<&str>> = TryRwLock::new_locked(Vec::new());
+    ///
+    /// assert!(CONFIG.try_read().is_none());
+    /// // The write lock has not been released yet, so nothing else can access the data.
+    /// unsafe { (*CONFIG.data_ptr()).push("verbose") };
+    /// unsafe { CONFIG.force_unlock() };
+    /// assert_eq!(*CONFIG.try_read().unwrap(), ["verbose"]);
+    /// ```
+    #[must_use]
+    pub const fn new_locked(data: T) -> Self {
+        Self {
+            state: CachePadded(AtomicUsize::new(WRITER)),
+            #[cfg(feature = "async")]
+            wakers: wakers::Waiters::new(),
+            #[cfg(feature = "stats")]
+            peak_readers: AtomicUsize::new(0),
+            data: UnsafeCell::new(data),
+        }
+    }
+}
+
+impl<'a, T: ?Sized> TryRwLock<&'a mut T> {
+    /// Create a lock around data that is already uniquely borrowed, without moving it.
+    ///
+    /// A `&mut T` cannot be reinterpreted as a `&mut TryRwLock<T>` since the lock stores its state
+    /// next to the data, so this instead locks the reference itself. Guards of the returned lock
+    /// dereference to the `&mut T`, and the borrow ends when the lock is dropped or
+    /// [unwrapped](Self::into_inner).
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use try_rwlock::TryRwLock;
+    ///
+    /// let mut value = 1;
+    /// let lock = TryRwLock::from_mut(&mut value);
+    /// **lock.try_write().unwrap() += 1;
+    /// drop(lock);
+    /// assert_eq!(value, 2);
+    /// ```
+    #[must_use]
+    pub const fn from_mut(data: &'a mut T) -> Self {
+        Self::new(data)
+    }
+}
+
+impl<T: ?Sized, const MAX_READERS: usize> TryRwLock<T, MAX_READERS> {
+    /// The maximum number of readers that can hold this lock at once.
+    ///
+    /// This is the `MAX_READERS` parameter, which defaults to `usize::MAX >> 3`. Using it also
+    /// checks that the parameter is within that limit, which leaves enough headroom in the lock's
+    /// state for readers that are backing out.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use try_rwlock::TryRwLock;
+    ///
+    /// const POOL_SIZE: usize = 64;
+    /// const _: () = assert!(POOL_SIZE <= TryRwLock::<()>::MAX_READERS);
+    /// ```
+    pub const MAX_READERS: usize = {
+        assert!(
+            MAX_READERS <= DEFAULT_MAX_READERS,
+            "`MAX_READERS` must be at most `usize::MAX >> 3`"
+        );
+        MAX_READERS
+    };
+
+    /// Create a new unlocked `TryRwLock<T>` that allows at most `MAX_READERS` readers at once.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use try_rwlock::TryRwLock;
+    ///
+    /// let lock = TryRwLock::<_, 2>::with_max_readers(0);
+    /// let reader_1 = lock.try_read().unwrap();
+    /// let reader_2 = lock.try_read().unwrap();
+    /// assert!(lock.try_read().is_none());
+    /// ```
+    ///
+    /// A limit above `usize::MAX >> 3` is rejected:
+    ///
+    /// ```compile_fail
+    /// use try_rwlock::TryRwLock;
+    ///
+    /// let lock = TryRwLock::<_, { usize::MAX }>::with_max_readers(0);
+    /// let reader = lock.try_read();
+    /// ```
+    #[must_use]
+    pub const fn with_max_readers(data: T) -> Self
+    where
+        T: Sized,
+    {
         Self {
-            readers: AtomicUsize::new(0),
+            state: CachePadded(AtomicUsize::new(0)),
+            #[cfg(feature = "async")]
+            wakers: wakers::Waiters::new(),
+            #[cfg(feature = "stats")]
+            peak_readers: AtomicUsize::new(0),
             data: UnsafeCell::new(data),
         }
     }
 
     /// Attempt to lock this `TryRwLock` with shared read access.
     ///
-    /// If the lock is currently being written to or there are `usize::MAX` existing readers, this
-    /// function will return `None`.
-    pub fn try_read(&self) -> Option<ReadGuard<'_, T>> {
-        self.readers
-            .fetch_update(
+    /// If the lock is currently being written to or there are already
+    /// [`MAX_READERS`](Self::MAX_READERS) readers, this function will return `None`.
+    ///
+    /// # Examples
+    ///
+    /// Discarding the result would unlock the lock straight away, so it is a warning:
+    ///
+    /// ```compile_fail
+    /// #![deny(unused_must_use)]
+    /// use try_rwlock::TryRwLock;
+    ///
+    /// let lock = TryRwLock::new(0);
+    /// lock.try_read();
+    /// ```
+    #[must_use = "if unused the TryRwLock will immediately unlock"]
+    pub fn try_read(&self) -> Option<ReadGuard<'_, T, T, MAX_READERS>> {
+        self.try_read_err().ok()
+    }
+
+    /// Attempt to lock this `TryRwLock` with shared read access, reporting why it failed.
+    ///
+    /// # Errors
+    ///
+    /// Returns [`TryLockError::WriteLocked`] if the lock is currently being written to, or
+    /// [`TryLockError::ReaderSaturated`] if there are already the maximum number of readers.
+    pub fn try_read_err(&self) -> Result<ReadGuard<'_, T, T, MAX_READERS>, TryLockError> {
+        self.lock_shared().map(|()| unsafe { ReadGuard::new(self) })
+    }
+
+    /// Make a single attempt at locking this `TryRwLock` with shared read access, which may fail
+    /// spuriously.
+    ///
+    /// This performs exactly one weak compare-exchange, so it can return `None` even if the lock
+    /// is free, for example if another reader changed the state at the same time. It is intended
+    /// for callers that retry in their own loop and want to control the backoff themselves; use
+    /// [`try_read`](Self::try_read) when `None` should mean that the lock really is unavailable.
+    #[must_use = "if unused the TryRwLock will immediately unlock"]
+    pub fn try_read_weak(&self) -> Option<ReadGuard<'_, T, T, MAX_READERS>> {
+        let state = self.state.load(atomic::Ordering::Relaxed);
+        if state & WRITER != 0 || state & READERS >= Self::MAX_READERS {
+            return None;
+        }
+        self.state
+            .compare_exchange_weak(
+                state,
+                state + 1,
                 atomic::Ordering::Acquire,
                 atomic::Ordering::Relaxed,
-                |readers| readers.checked_add(1),
             )
             .ok()
-            .map(|_| unsafe { ReadGuard::new(self) })
+            .map(|_| {
+                self.record_readers((state & READERS) + 1);
+                unsafe { ReadGuard::new(self) }
+            })
     }
 
     /// Attempt to lock this `TryRwLock` with unique write access.
     ///
     /// If the lock is currently being written to or read from, this function will return `None`.
-    pub fn try_write(&self) -> Option<WriteGuard<'_, T>> {
-        self.readers
-            .compare_exchange(
-                0,
-                usize::MAX,
-                atomic::Ordering::Acquire,
-                atomic::Ordering::Relaxed,
-            )
-            .ok()
-            .map(|_| unsafe { WriteGuard::new(self) })
+    /// It may also return `None` if a failed reader has not yet backed out of the lock.
+    #[must_use = "if unused the TryRwLock will immediately unlock"]
+    pub fn try_write(&self) -> Option<WriteGuard<'_, T, T, MAX_READERS>> {
+        self.try_write_err().ok()
     }
 
-    /// Get the underlying data of the lock.
-    #[must_use]
-    pub fn into_inner(self) -> T {
-        self.data.into_inner()
+    /// Attempt to lock this `TryRwLock` with unique write access, reporting why it failed.
+    ///
+    /// # Errors
+    ///
+    /// Returns [`TryLockError::WriteLocked`] if the lock is currently being written to, or
+    /// [`TryLockError::ReadLocked`] if it is currently being read from.
+    pub fn try_write_err(&self) -> Result<WriteGuard<'_, T, T, MAX_READERS>, TryLockError> {
+        self.lock_exclusive()
+            .map(|()| unsafe { WriteGuard::new(self) })
     }
 
-    /// Get a mutable reference to the underlying data.
+    /// Lock this `TryRwLock` with shared read access, spinning until it can be acquired.
     ///
-    /// As this method takes a mutable reference, no locking needs to take place.
-    #[must_use]
-    pub fn get_mut(&mut self) -> &mut T {
-        self.data.get_mut()
+    /// This repeatedly calls [`try_read`](Self::try_read), backing off exponentially and then
+    /// yielding to the OS scheduler between attempts. The lock is not fair, so if writers hold it
+    /// continuously this may never return.
+    ///
+    /// Requires the `std` feature.
+    #[cfg(feature = "std")]
+    pub fn read(&self) -> ReadGuard<'_, T, T, MAX_READERS> {
+        spin_until(|| self.try_read())
     }
 
-    /// Check if the lock is currently locked in any way.
-    #[must_use]
-    pub fn is_locked(&self) -> bool {
-        self.readers.load(atomic::Ordering::Acquire) != 0
+    /// Lock this `TryRwLock` with unique write access, spinning until it can be acquired.
+    ///
+    /// This repeatedly calls [`try_write`](Self::try_write), backing off exponentially and then
+    /// yielding to the OS scheduler between attempts. The lock is not fair, so if readers
+    /// continuously hold it this may never return.
+    ///
+    /// Requires the `std` feature.
+    #[cfg(feature = "std")]
+    pub fn write(&self) -> WriteGuard<'_, T, T, MAX_READERS> {
+        spin_until(|| self.try_write())
     }
 
-    /// Check if the lock is currently locked for writing, or if there are `usize::MAX` readers.
-    #[must_use]
-    pub fn is_write_locked(&self) -> bool {
-        self.readers.load(atomic::Ordering::Acquire) == usize::MAX
+    /// Attempt to lock this `TryRwLock` with shared read access, retrying up to `max_spins` times
+    /// if it is being written to.
+    ///
+    /// This is a bounded version of `read` that only ever spins, so unlike `read` it is available
+    /// without the `std` feature. If the lock still cannot be acquired after every attempt, this
+    /// function will return `None`.
+    #[must_use = "if unused the TryRwLock will immediately unlock"]
+    pub fn spin_read(&self, max_spins: usize) -> Option<ReadGuard<'_, T, T, MAX_READERS>> {
+        spin_for(max_spins, || self.try_read())
     }
-}
 
-impl<T: Debug> Debug for TryRwLock<T> {
-    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
-        #[allow(clippy::option_if_let_else)]
-        if let Some(guard) = self.try_read() {
-            f.debug_struct("TryRwLock").field("data", &*guard).finish()
-        } else {
-            struct LockedPlaceholder;
-            impl Debug for LockedPlaceholder {
-                fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
-                    f.write_str("<locked>")
-                }
-            }
+    /// Attempt to lock this `TryRwLock` with unique write access, retrying up to `max_spins` times
+    /// if it is being written to or read from.
+    ///
+    /// This is a bounded version of `write` that only ever spins, so unlike `write` it is
+    /// available without the `std` feature. If the lock still cannot be acquired after every
+    /// attempt, this function will return `None`.
+    #[must_use = "if unused the TryRwLock will immediately unlock"]
+    pub fn spin_write(&self, max_spins: usize) -> Option<WriteGuard<'_, T, T, MAX_READERS>> {
+        spin_for(max_spins, || self.try_write())
+    }
 
-            f.debug_struct("TryRwLock")
-                .field("data", &LockedPlaceholder)
-                .finish()
+    /// Lock this `TryRwLock` with shared read access, like [`RefCell::borrow`].
+    ///
+    /// # Panics
+    ///
+    /// Panics if the lock is currently being written to, or if there are already `MAX_READERS`
+    /// readers. For a non-panicking variant, use [`try_borrow`](Self::try_borrow).
+    ///
+    /// [`RefCell::borrow`]: core::cell::RefCell::borrow
+    #[track_caller]
+    pub fn borrow(&self) -> ReadGuard<'_, T, T, MAX_READERS> {
+        let error = match self.try_read_err() {
+            Ok(guard) => return guard,
+            Err(error) => error,
+        };
+        match error {
+            TryLockError::WriteLocked => panic!("already write-locked"),
+            _ => panic!("too many readers"),
         }
     }
-}
 
-impl<T> From<T> for TryRwLock<T> {
-    fn from(data: T) -> Self {
-        Self::new(data)
+    /// Lock this `TryRwLock` with unique write access, like [`RefCell::borrow_mut`].
+    ///
+    /// # Panics
+    ///
+    /// Panics if the lock is currently being written to or read from. For a non-panicking
+    /// variant, use [`try_borrow_mut`](Self::try_borrow_mut).
+    ///
+    /// [`RefCell::borrow_mut`]: core::cell::RefCell::borrow_mut
+    #[track_caller]
+    pub fn borrow_mut(&self) -> WriteGuard<'_, T, T, MAX_READERS> {
+        self.try_write().expect("already locked")
     }
-}
 
-// This implementation requires `T` to be `Send` because it owns a `T`, allows unique access to it
-// and destroys it in its destructor.
-unsafe impl<T: Send> Send for TryRwLock<T> {}
+    /// Attempt to lock this `TryRwLock` with shared read access, like [`RefCell::try_borrow`].
+    ///
+    /// This is the same as [`try_read_err`](Self::try_read_err). Rather than a separate
+    /// `BorrowError` type, it fails with the same [`TryLockError`] as the other locking methods.
+    ///
+    /// # Errors
+    ///
+    /// Fails if the lock is currently being written to or already has `MAX_READERS` readers.
+    ///
+    /// [`RefCell::try_borrow`]: core::cell::RefCell::try_borrow
+    pub fn try_borrow(&self) -> Result<ReadGuard<'_, T, T, MAX_READERS>, TryLockError> {
+        self.try_read_err()
+    }
 
-// `T` is required to be `Send` because this type allows upgrading `&TryRwLock<T>` to `&mut T`,
-// which means it can be dropped on a different thread. `T` is additionally required to be `Sync`
-// because it allows the user to obtain an `&T`.
-unsafe impl<T: Send + Sync> Sync for TryRwLock<T> {}
+    /// Attempt to lock this `TryRwLock` with unique write access, like
+    /// [`RefCell::try_borrow_mut`].
+    ///
+    /// This is the same as [`try_write_err`](Self::try_write_err).
+    ///
+    /// # Errors
+    ///
+    /// Fails if the lock is currently being written to or read from.
+    ///
+    /// [`RefCell::try_borrow_mut`]: core::cell::RefCell::try_borrow_mut
+    pub fn try_borrow_mut(&self) -> Result<WriteGuard<'_, T, T, MAX_READERS>, TryLockError> {
+        self.try_write_err()
+    }
 
-/// A RAII guard that guarantees shared read access to a `TryRwLock`.
-#[must_use = "if unused the TryRwLock will immediately unlock"]
-pub struct ReadGuard<'lock, T, U = T> {
-    data: NonNull<U>,
-    lock: &'lock TryRwLock<T>,
-    // This also enforces a `U: 'lock` bound, which is necessary for soundness (see
-    // https://github.com/SabrinaJewson/try-rwlock.rs/issues/2)
-    _covariant_over_u: PhantomData<&'lock U>,
-}
+    /// Attempt to read-lock this `TryRwLock` and copy its data, the counterpart of
+    /// [`set`](Self::set).
+    ///
+    /// If the lock is currently being written to, this function will return `None`. Use
+    /// [`get_cloned`](Self::get_cloned) for data that is not `Copy`.
+    #[must_use]
+    pub fn get(&self) -> Option<T>
+    where
+        T: Copy,
+    {
+        self.try_read().map(|guard| *guard)
+    }
 
-// As we expose a `TryRwlock<T>`, we first inherit the bounds for `&TryRwLock<T>: Sync` on T; as we
-// give shared access to the `U`, we use the same bounds as `&U` on `U`.
-unsafe impl<T: Send + Sync, U: Sync> Send for ReadGuard<'_, T, U> {}
-unsafe impl<T: Send + Sync, U: Sync> Sync for ReadGuard<'_, T, U> {}
+    /// Attempt to read-lock this `TryRwLock` and clone its data.
+    ///
+    /// If the lock is currently being written to, this function will return `None`.
+    #[must_use]
+    pub fn get_cloned(&self) -> Option<T>
+    where
+        T: Clone,
+    {
+        self.try_read().map(|guard| guard.clone())
+    }
 
-impl<'lock, T> ReadGuard<'lock, T> {
-    unsafe fn new(lock: &'lock TryRwLock<T>) -> Self {
-        Self {
-            data: NonNull::new(lock.data.get()).expect("`UnsafeCell::get` never returns null"),
-            lock,
-            _covariant_over_u: PhantomData,
-        }
+    /// Attempt to read-lock this `TryRwLock` and clone its data into `dst`, returning whether it
+    /// succeeded.
+    ///
+    /// This uses [`Clone::clone_from`], so types such as `Vec` and `String` can reuse the
+    /// allocation already in `dst`. If the lock is currently being written to, `dst` is left
+    /// unchanged and this function will return `false`.
+    #[must_use]
+    pub fn try_clone_into(&self, dst: &mut T) -> bool
+    where
+        T: Clone,
+    {
+        self.try_read()
+            .map(|guard| dst.clone_from(&guard))
+            .is_some()
     }
-}
 
-impl<'lock, T, U> ReadGuard<'lock, T, U> {
-    /// Get a shared reference to the lock that this read guard has locked.
+    /// Attempt to read-lock this `TryRwLock` and copy its data out.
+    ///
+    /// The lock is only held for the duration of the copy, so no guard can be kept alive by
+    /// accident. If the lock is currently being written to, this function will return `None`.
     #[must_use]
-    pub fn rwlock(guard: &Self) -> &'lock TryRwLock<T> {
-        guard.lock
+    pub fn try_load(&self) -> Option<T>
+    where
+        T: Copy,
+    {
+        self.get()
     }
 
-    /// Attempt to upgrade the `ReadGuard` to a `WriteGuard`.
+    /// Attempt to write-lock this `TryRwLock` and overwrite its data with `value`, returning
+    /// whether it succeeded.
     ///
-    /// Note that for soundness reasons, this will undo any [`map`](Self::map)ping that has
-    /// previously been applied.
+    /// Like [`try_load`](Self::try_load), the lock is only held while `value` is stored. If the
+    /// lock is currently being written to or read from, the data is left unchanged and this
+    /// function will return `false`.
+    #[must_use]
+    pub fn try_store(&self, value: T) -> bool
+    where
+        T: Copy,
+    {
+        self.set(value).is_ok()
+    }
+
+    /// Attempt to write-lock this `TryRwLock` and overwrite its data with `value`.
     ///
     /// # Errors
     ///
-    /// Fails if there is more than one reader currently using the lock.
-    pub fn try_upgrade(guard: Self) -> Result<WriteGuard<'lock, T>, Self> {
-        match guard.lock.readers.compare_exchange(
-            1,
-            usize::MAX,
-            atomic::Ordering::Acquire,
-            atomic::Ordering::Relaxed,
-        ) {
-            Ok(_) => {
-                let guard = ManuallyDrop::new(guard);
-                Ok(unsafe { WriteGuard::new(guard.lock) })
+    /// If the lock is currently being written to or read from, `value` is handed back.
+    pub fn set(&self, value: T) -> Result<(), T>
+    where
+        T: Sized,
+    {
+        match self.try_write() {
+            Some(mut guard) => {
+                *guard = value;
+                Ok(())
             }
-            Err(_) => Err(guard),
+            None => Err(value),
         }
     }
 
-    /// Map to another value and keep locked.
-    pub fn map<V>(guard: Self, f: impl FnOnce(&U) -> &V) -> ReadGuard<'lock, T, V> {
-        let guard = ManuallyDrop::new(guard);
-        ReadGuard {
-            data: NonNull::from(f(&**guard)),
-            lock: guard.lock,
-            _covariant_over_u: PhantomData,
+    /// Attempt to write-lock this `TryRwLock` and replace its data with `value`, returning the old
+    /// data.
+    ///
+    /// # Errors
+    ///
+    /// If the lock is currently being written to or read from, `value` is handed back, as in
+    /// [`set`](Self::set).
+    pub fn replace(&self, value: T) -> Result<T, T>
+    where
+        T: Sized,
+    {
+        match self.try_write() {
+            Some(mut guard) => Ok(mem::replace(&mut *guard, value)),
+            None => Err(value),
         }
     }
 
-    /// Undo any previous mapping applied, returning the guard back to its original state.
-    pub fn unmap(guard: Self) -> ReadGuard<'lock, T> {
-        let guard = ManuallyDrop::new(guard);
-        unsafe { ReadGuard::new(guard.lock) }
+    /// Attempt to write-lock this `TryRwLock` and take its data, leaving `T::default()` in its
+    /// place.
+    ///
+    /// If the lock is currently being written to or read from, this function will return `None`.
+    pub fn take(&self) -> Option<T>
+    where
+        T: Default,
+    {
+        self.try_write().map(|mut guard| mem::take(&mut *guard))
     }
-}
-
-impl<T, U> Deref for ReadGuard<'_, T, U> {
-    type Target = U;
 
-    fn deref(&self) -> &Self::Target {
-        unsafe { self.data.as_ref() }
+    /// Attempt to write-lock this `TryRwLock` and `other` and swap their data, returning whether
+    /// it succeeded.
+    ///
+    /// The two locks are always locked in address order. If either of them is currently being
+    /// written to or read from, neither is left locked or changed and this function will return
+    /// `false`. Swapping a lock with itself does nothing, and succeeds if the lock could be
+    /// write-locked.
+    #[must_use]
+    pub fn swap(&self, other: &Self) -> bool
+    where
+        T: Sized,
+    {
+        if Self::ref_eq(self, other) {
+            return self.try_write().is_some();
+        }
+        let (first, second) = if ::core::ptr::from_ref(self) < ::core::ptr::from_ref(other) {
+            (self, other)
+        } else {
+            (other, self)
+        };
+        match try_write2(first, second) {
+            Some((mut first, mut second)) => {
+                mem::swap(&mut *first, &mut *second);
+                true
+            }
+            None => false,
+        }
     }
-}
 
-impl<T, U> Drop for ReadGuard<'_, T, U> {
-    fn drop(&mut self) {
-        self.lock.readers.fetch_sub(1, atomic::Ordering::Release);
+    /// Attempt to write-lock this `TryRwLock` and store `new` in it if its data is equal to
+    /// `current`.
+    ///
+    /// This is a coarse version of [`AtomicUsize::compare_exchange`] for data of any size. On
+    /// success, the previous value, which is equal to `current`, is returned.
+    ///
+    /// # Errors
+    ///
+    /// Returns `Err(Some(actual))` with the current data if it was not equal to `current`, or
+    /// `Err(None)` if the lock is currently being written to or read from.
+    pub fn compare_exchange(&self, current: T, new: T) -> Result<T, Option<T>>
+    where
+        T: Copy + PartialEq,
+    {
+        let mut guard = self.try_write().ok_or(None)?;
+        if *guard == current {
+            Ok(mem::replace(&mut *guard, new))
+        } else {
+            Err(Some(*guard))
+        }
     }
-}
 
-impl<T, U: Debug> Debug for ReadGuard<'_, T, U> {
-    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
-        f.debug_struct("TryRwLockReadGuard")
-            .field("data", &**self)
-            .finish()
+    /// Attempt to write-lock this `TryRwLock` and replace its data with the result of a closure.
+    ///
+    /// This mirrors [`AtomicUsize::fetch_update`]: `f` is given the current data and may return
+    /// `Some` with a new value to store, in which case a clone of the previous data is returned.
+    ///
+    /// # Errors
+    ///
+    /// Returns `Err(Some(previous))` with a clone of the data if `f` returned `None`, leaving the
+    /// data unchanged, or `Err(None)` if the lock is currently being written to or read from.
+    pub fn fetch_update(&self, f: impl FnOnce(&T) -> Option<T>) -> Result<T, Option<T>>
+    where
+        T: Clone,
+    {
+        let mut guard = self.try_write().ok_or(None)?;
+        let previous = guard.clone();
+        match f(&guard) {
+            Some(new) => {
+                *guard = new;
+                Ok(previous)
+            }
+            None => Err(Some(previous)),
+        }
     }
-}
 
-impl<T, U: Display> Display for ReadGuard<'_, T, U> {
-    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
-        Display::fmt(&**self, f)
+    /// Attempt to write-lock this `TryRwLock` and add `value` to its data, returning the previous
+    /// data.
+    ///
+    /// This is a coarse version of [`AtomicUsize::fetch_add`] for any type that supports `+=`,
+    /// and behaves like `+=` on overflow. If the lock is currently being written to or read from,
+    /// the data is left unchanged and this function will return `None`.
+    pub fn fetch_add(&self, value: T) -> Option<T>
+    where
+        T: AddAssign + Copy,
+    {
+        let mut guard = self.try_write()?;
+        let previous = *guard;
+        *guard += value;
+        Some(previous)
     }
-}
 
-/// A RAII guard that guarantees unique write access to a `TryRwLock`.
-#[must_use = "if unused the TryRwLock will immediately unlock"]
-pub struct WriteGuard<'lock, T, U = T> {
-    data: NonNull<U>,
-    lock: &'lock TryRwLock<T>,
-    _invariant_over_u: PhantomData<&'lock mut U>,
-}
+    /// Attempt to write-lock this `TryRwLock` and subtract `value` from its data, returning the
+    /// previous data.
+    ///
+    /// Like [`fetch_add`](Self::fetch_add), this behaves like `-=` on overflow and returns `None`
+    /// if the lock is currently being written to or read from.
+    pub fn fetch_sub(&self, value: T) -> Option<T>
+    where
+        T: SubAssign + Copy,
+    {
+        let mut guard = self.try_write()?;
+        let previous = *guard;
+        *guard -= value;
+        Some(previous)
+    }
 
-// As we expose a `TryRwlock<T>`, we first inherit the bounds for `&TryRwLock<T>: Sync` on T; as we
-// give exclusive access to the `U`, we use the same bounds as `&mut U` on `U`.
-unsafe impl<T: Send + Sync, U: Send> Send for WriteGuard<'_, T, U> {}
-unsafe impl<T: Send + Sync, U: Sync> Sync for WriteGuard<'_, T, U> {}
+    /// Attempt to write-lock this `TryRwLock` and modify its data with a closure.
+    ///
+    /// The lock is released as soon as the closure returns. If the lock is currently being written
+    /// to or read from, the closure is not called and this function will return `None`.
+    pub fn update<R>(&self, f: impl FnOnce(&mut T) -> R) -> Option<R> {
+        self.try_write().map(|mut guard| f(&mut guard))
+    }
 
-impl<'lock, T> WriteGuard<'lock, T> {
-    unsafe fn new(lock: &'lock TryRwLock<T>) -> Self {
-        Self {
-            data: NonNull::new(lock.data.get()).expect("`UnsafeCell::get` never returns null"),
-            lock,
-            _invariant_over_u: PhantomData,
+    /// Attempt to write-lock this `TryRwLock` and replace its data with the result of a closure
+    /// that takes it by value.
+    ///
+    /// The data is moved out of the lock for the duration of the closure, so there is nothing to
+    /// put back if it panics. In that case the process is aborted instead of unwinding.
+    ///
+    /// # Errors
+    ///
+    /// If the lock is currently being written to or read from, the closure is not called and the
+    /// reason is returned, as in [`try_write_err`](Self::try_write_err).
+    pub fn replace_with(&self, f: impl FnOnce(T) -> T) -> Result<(), TryLockError>
+    where
+        T: Sized,
+    {
+        /// Aborts the process if dropped, by panicking while the closure is already unwinding.
+        struct AbortOnUnwind;
+
+        impl Drop for AbortOnUnwind {
+            fn drop(&mut self) {
+                panic!("the closure passed to `TryRwLock::replace_with` panicked");
+            }
         }
-    }
-}
 
-impl<'lock, T, U> WriteGuard<'lock, T, U> {
-    /// Get a shared reference to the lock that this write guard has locked.
-    #[must_use]
-    pub fn rwlock(guard: &Self) -> &'lock TryRwLock<T> {
-        guard.lock
+        let mut guard = self.try_write_err()?;
+        let data = WriteGuard::as_mut_ptr(&mut guard);
+        let bomb = AbortOnUnwind;
+        // The write lock gives us unique access to the data, and it is only left
+        // uninitialized while `f` runs, during which any unwinding is turned into an abort.
+        unsafe { data.write(f(data.read())) };
+        mem::forget(bomb);
+        Ok(())
     }
 
-    /// Downgrade the `WriteGuard` to a `ReadGuard`.
+    /// Attempt to read-lock this `TryRwLock` and run a closure on its data.
     ///
-    /// Note that for soundness reasons, this will undo any [`map`](Self::map)ing that has
-    /// previously been applied.
-    pub fn downgrade(guard: Self) -> ReadGuard<'lock, T> {
-        let guard = ManuallyDrop::new(guard);
-        guard.lock.readers.store(1, atomic::Ordering::Release);
-        unsafe { ReadGuard::new(guard.lock) }
+    /// The lock is released as soon as the closure returns. If the lock is currently being written
+    /// to, the closure is not called and this function will return `None`.
+    ///
+    /// The closure must accept a reference of any lifetime, and `R` is chosen before that lifetime
+    /// is known, so neither the return value nor anything the closure captures can hold on to the
+    /// reference after the lock is released.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use try_rwlock::TryRwLock;
+    ///
+    /// let lock = TryRwLock::new(vec![1, 2, 3]);
+    /// assert_eq!(lock.with_read(|v| v.len()), Some(3));
+    /// ```
+    ///
+    /// Returning the reference does not compile:
+    ///
+    /// ```compile_fail
+    /// use try_rwlock::TryRwLock;
+    ///
+    /// let lock = TryRwLock::new(vec![1, 2, 3]);
+    /// let escaped = lock.with_read(|v| v);
+    /// ```
+    ///
+    /// Neither does storing it somewhere outside the closure:
+    ///
+    /// ```compile_fail
+    /// use try_rwlock::TryRwLock;
+    ///
+    /// let lock = TryRwLock::new(vec![1, 2, 3]);
+    /// let mut escaped = None;
+    /// lock.with_read(|v| escaped = Some(v));
+    /// ```
+    pub fn with_read<R>(&self, f: impl FnOnce(&T) -> R) -> Option<R> {
+        self.try_read().map(|guard| f(&guard))
     }
 
-    /// Map to another value and keep locked.
-    pub fn map<V>(guard: Self, f: impl FnOnce(&mut U) -> &mut V) -> WriteGuard<'lock, T, V> {
-        let mut guard = ManuallyDrop::new(guard);
-        WriteGuard {
-            data: NonNull::from(f(&mut **guard)),
-            lock: guard.lock,
-            _invariant_over_u: PhantomData,
+    /// Attempt to write-lock this `TryRwLock` and run a closure on its data.
+    ///
+    /// This is the same as [`update`](Self::update), named to pair with
+    /// [`with_read`](Self::with_read). Like it, the reference cannot escape the closure:
+    ///
+    /// ```compile_fail
+    /// use try_rwlock::TryRwLock;
+    ///
+    /// let lock = TryRwLock::new(vec![1, 2, 3]);
+    /// let escaped = lock.with_write(|v| v);
+    /// ```
+    pub fn with_write<R>(&self, f: impl FnOnce(&mut T) -> R) -> Option<R> {
+        self.update(f)
+    }
+
+    /// Attempt to read-lock this `TryRwLock` and compute a value from its data.
+    ///
+    /// This is the same as [`with_read`](Self::with_read), named to pair with
+    /// [`read_map_or`](Self::read_map_or) and [`read_map_or_else`](Self::read_map_or_else).
+    pub fn read_map<R>(&self, f: impl FnOnce(&T) -> R) -> Option<R> {
+        self.with_read(f)
+    }
+
+    /// Attempt to read-lock this `TryRwLock` and compute a value from its data, or return
+    /// `default` if it is currently being written to.
+    ///
+    /// Like [`Option::map_or`], `default` is evaluated eagerly; use
+    /// [`read_map_or_else`](Self::read_map_or_else) to compute it only when needed.
+    pub fn read_map_or<R>(&self, default: R, f: impl FnOnce(&T) -> R) -> R {
+        self.with_read(f).unwrap_or(default)
+    }
+
+    /// Attempt to read-lock this `TryRwLock` and compute a value from its data, or call `default`
+    /// if it is currently being written to.
+    pub fn read_map_or_else<R>(&self, default: impl FnOnce() -> R, f: impl FnOnce(&T) -> R) -> R {
+        self.with_read(f).unwrap_or_else(default)
+    }
+
+    /// Attempt to read-lock this `TryRwLock` and run `on_read` on its data, or run `on_busy` if
+    /// it is currently being written to.
+    ///
+    /// This is [`read_map_or_else`](Self::read_map_or_else) with the closures the other way
+    /// around, to pair with [`write_or_else`](Self::write_or_else).
+    pub fn read_or_else<R>(&self, on_read: impl FnOnce(&T) -> R, on_busy: impl FnOnce() -> R) -> R {
+        self.read_map_or_else(on_busy, on_read)
+    }
+
+    /// Attempt to write-lock this `TryRwLock` and run `on_write` on its data, or run `on_busy` if
+    /// it is currently being written to or read from.
+    pub fn write_or_else<R>(
+        &self,
+        on_write: impl FnOnce(&mut T) -> R,
+        on_busy: impl FnOnce() -> R,
+    ) -> R {
+        self.update(on_write).unwrap_or_else(on_busy)
+    }
+
+    /// Attempt to write-lock this `TryRwLock`, run a closure on its data and then keep it locked
+    /// for reading.
+    ///
+    /// The write lock is [downgraded](WriteGuard::downgrade) in place, so no other writer can
+    /// modify the data between the closure and the returned guard. If the lock is currently being
+    /// written to or read from, the closure is not called and this function will return `None`.
+    #[must_use = "if unused the TryRwLock will immediately unlock"]
+    pub fn try_modify_then_read(
+        &self,
+        f: impl FnOnce(&mut T),
+    ) -> Option<ReadGuard<'_, T, T, MAX_READERS>> {
+        self.try_write().map(|mut guard| {
+            f(&mut guard);
+            WriteGuard::downgrade(guard)
+        })
+    }
+
+    /// Attempt to lock this `TryRwLock` with upgradable read access.
+    ///
+    /// An upgradable read lock coexists with ordinary readers, but there can only be one at a time
+    /// and it is the only kind of reader that can be guaranteed to eventually upgrade to a writer,
+    /// since ordinary readers cannot upgrade while it is held.
+    ///
+    /// If the lock is currently being written to, already has an upgradable reader or already has
+    /// the maximum number of readers, this function will return `None`.
+    #[must_use = "if unused the TryRwLock will immediately unlock"]
+    pub fn try_upgradable_read(&self) -> Option<UpgradableReadGuard<'_, T, MAX_READERS>> {
+        self.lock_upgradable()
+            .then(|| UpgradableReadGuard { lock: self })
+    }
+
+    /// Get the underlying data of the lock.
+    #[must_use]
+    pub fn into_inner(self) -> T
+    where
+        T: Sized,
+    {
+        self.data.into_inner()
+    }
+
+    /// Get a mutable reference to the underlying data.
+    ///
+    /// As this method takes a mutable reference, no locking needs to take place.
+    #[must_use]
+    pub fn get_mut(&mut self) -> &mut T {
+        self.data.get_mut()
+    }
+
+    /// Get a raw pointer to the underlying data.
+    ///
+    /// This does not lock the lock. Obtaining the pointer is safe, but dereferencing it is only
+    /// sound when the lock's rules would allow the same access: reading through it must not overlap
+    /// with a writer, and writing through it must not overlap with any other access, including
+    /// through guards.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use try_rwlock::TryRwLock;
+    ///
+    /// let lock = TryRwLock::new(5);
+    /// let ptr = lock.data_ptr();
+    ///
+    /// // No guards exist and the lock is not shared, so nothing else can access the data.
+    /// unsafe { *ptr = 6 };
+    /// assert_eq!(*lock.try_read().unwrap(), 6);
+    /// ```
+    #[must_use]
+    pub fn data_ptr(&self) -> *mut T {
+        self.data.get()
+    }
+
+    /// Release a write lock that is not held by any guard, such as the one a lock created with
+    /// [`new_locked`](TryRwLock::new_locked) starts with.
+    ///
+    /// # Safety
+    ///
+    /// The lock must be write-locked, and the caller must be responsible for that write lock: it
+    /// must not belong to a [`WriteGuard`] or [`SplitWriteGuard`] that is still alive, and
+    /// nothing may access the data through it after this call.
+    pub unsafe fn force_unlock(&self) {
+        unsafe { self.unlock_exclusive() };
+    }
+
+    /// Release a write lock whose guard was lost, for example with [`WriteGuard::leak`] or
+    /// [`mem::forget`].
+    ///
+    /// This is the same as [`force_unlock`](Self::force_unlock), named to pair with
+    /// [`force_unlock_read`](Self::force_unlock_read). It removes exactly the writer from the
+    /// lock's state rather than resetting it, so readers that are backing out are not lost.
+    ///
+    /// # Safety
+    ///
+    /// The same as for [`force_unlock`](Self::force_unlock). In particular, any reference
+    /// obtained from a leaked guard must never be used again.
+    pub unsafe fn force_unlock_write(&self) {
+        unsafe { self.force_unlock() };
+    }
+
+    /// Release one read lock whose guard was lost, for example with [`ReadGuard::leak`] or
+    /// [`mem::forget`].
+    ///
+    /// # Safety
+    ///
+    /// The lock must be read-locked, and the caller must be responsible for the read lock being
+    /// released: it must not belong to a [`ReadGuard`] that is still alive or will be rebuilt, or
+    /// to an [`UpgradableReadGuard`] at all, and any reference obtained from a leaked guard must
+    /// never be used again. Calling this once for every lost guard is fine, but releasing a reader
+    /// more times than guards were lost lets a writer in while another reader still has access to
+    /// the data.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use try_rwlock::{ReadGuard, TryRwLock};
+    ///
+    /// let lock = TryRwLock::new(1);
+    /// let data = ReadGuard::leak(lock.try_read().unwrap());
+    /// assert_eq!(*data, 1);
+    /// assert!(lock.try_write().is_none());
+    ///
+    /// // Exactly one guard was leaked, and `data` is not used after this point.
+    /// unsafe { lock.force_unlock_read() };
+    /// assert!(lock.try_write().is_some());
+    /// ```
+    pub unsafe fn force_unlock_read(&self) {
+        unsafe { self.unlock_shared() };
+    }
+
+    /// Get a shared reference to the data without locking the lock.
+    ///
+    /// Unlike [`try_read`](Self::try_read), this does not touch the lock's state at all, so it
+    /// can be used by diagnostic tools that must not disturb the lock, such as an inspector
+    /// taking a snapshot of the data.
+    ///
+    /// # Safety
+    ///
+    /// No write access to the data may happen while the returned reference is alive. That is, the
+    /// lock must not be write-locked when this is called, and must not be write-locked by anyone
+    /// until the reference is dropped, including through an existing [`WriteGuard`] or
+    /// [`UpgradableReadGuard`] being upgraded. Otherwise the reference would alias a unique
+    /// reference, which is undefined behavior even if the data is never actually modified.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use try_rwlock::TryRwLock;
+    ///
+    /// let lock = TryRwLock::new(5);
+    /// let reader = lock.try_read().unwrap();
+    ///
+    /// // The lock is read-locked and only this thread has access to it, so no writer can get in
+    /// // while `data` is alive.
+    /// let data = unsafe { lock.peek_unchecked() };
+    /// assert_eq!(*data, 5);
+    /// assert_eq!(lock.reader_count(), 1);
+    /// drop(reader);
+    /// ```
+    #[must_use]
+    pub unsafe fn peek_unchecked(&self) -> &T {
+        unsafe { &*self.data.get() }
+    }
+
+    /// Check whether two references point to the same `TryRwLock`.
+    ///
+    /// This compares addresses, not the contents of the locks, so unlike `==` it never locks
+    /// anything.
+    #[must_use]
+    pub fn ref_eq(a: &Self, b: &Self) -> bool {
+        ::core::ptr::addr_eq(a, b)
+    }
+
+    /// Get the number of readers currently holding the lock.
+    ///
+    /// An upgradable reader counts as a reader.
+    ///
+    /// This returns 0 if the lock is free or if it is locked for writing; use
+    /// [`is_write_locked`](Self::is_write_locked) to tell those two cases apart.
+    ///
+    /// The returned value is only a snapshot, and may be out of date by the time it is used.
+    #[must_use]
+    pub fn reader_count(&self) -> usize {
+        reader_count(self.state.load(atomic::Ordering::Acquire))
+    }
+
+    /// Get the number of readers currently holding the lock, without synchronizing with them.
+    ///
+    /// This is like [`reader_count`](Self::reader_count), but loads the state with `Relaxed`
+    /// ordering, which can be cheaper on weakly-ordered targets. The count is purely advisory: it
+    /// may be stale, and seeing a reader leave does not make its accesses to the data visible.
+    #[must_use]
+    pub fn reader_count_relaxed(&self) -> usize {
+        reader_count(self.state.load(atomic::Ordering::Relaxed))
+    }
+
+    /// Get the largest number of readers that have held the lock at once.
+    ///
+    /// This is only observational: it is updated whenever a reader acquires the lock, and under
+    /// contention may also count readers that were in the middle of failing to acquire it.
+    ///
+    /// Requires the `stats` feature.
+    #[cfg(feature = "stats")]
+    #[must_use]
+    pub fn peak_readers(&self) -> usize {
+        self.peak_readers.load(atomic::Ordering::Relaxed)
+    }
+
+    /// Get the number of writers currently holding the lock, which is either 0 or 1.
+    ///
+    /// Like [`reader_count`](Self::reader_count), this is only a snapshot of the lock's state.
+    #[must_use]
+    pub fn writer_count(&self) -> usize {
+        usize::from(self.is_write_locked())
+    }
+
+    /// Check if the lock is currently locked in any way.
+    ///
+    /// Like [`reader_count`](Self::reader_count), this is only a snapshot of the lock's state.
+    ///
+    /// # Examples
+    ///
+    /// Like the other state queries, calling this without looking at the result is a warning:
+    ///
+    /// ```compile_fail
+    /// #![deny(unused_must_use)]
+    /// use try_rwlock::TryRwLock;
+    ///
+    /// let lock = TryRwLock::new(0);
+    /// lock.is_locked();
+    /// ```
+    #[must_use]
+    pub fn is_locked(&self) -> bool {
+        self.state.load(atomic::Ordering::Acquire) != 0
+    }
+
+    /// Check if the lock is currently locked for reading.
+    ///
+    /// Like [`reader_count`](Self::reader_count), this is only a snapshot of the lock's state.
+    #[must_use]
+    pub fn is_read_locked(&self) -> bool {
+        self.reader_count() != 0
+    }
+
+    /// Check if the lock is currently locked for writing.
+    ///
+    /// Like [`reader_count`](Self::reader_count), this is only a snapshot of the lock's state.
+    #[must_use]
+    pub fn is_write_locked(&self) -> bool {
+        self.state.load(atomic::Ordering::Acquire) & WRITER != 0
+    }
+
+    /// Attempt to add a reader to the lock's state.
+    fn lock_shared(&self) -> Result<(), TryLockError> {
+        // Unlike a compare-exchange loop, this never has to retry under contention. If it turns
+        // out the reader is not allowed in, back out again.
+        let state = self.state.fetch_add(1, atomic::Ordering::Acquire);
+        let error = if state & WRITER != 0 {
+            TryLockError::WriteLocked
+        } else if state & READERS >= Self::MAX_READERS {
+            TryLockError::ReaderSaturated
+        } else {
+            self.record_readers((state & READERS) + 1);
+            return Ok(());
+        };
+        let state = self.state.fetch_sub(1, atomic::Ordering::Relaxed);
+        // The lock may have been released while this reader was in it.
+        self.reader_backed_out(state - 1);
+        Err(error)
+    }
+
+    /// Attempt to add an upgradable reader to the lock's state, returning whether it succeeded.
+    fn lock_upgradable(&self) -> bool {
+        let mut state = self.state.load(atomic::Ordering::Relaxed);
+        let mut backoff = Backoff::new();
+        loop {
+            if state & (WRITER | UPGRADABLE) != 0 || state & READERS >= Self::MAX_READERS {
+                return false;
+            }
+            match self.state.compare_exchange_weak(
+                state,
+                (state + 1) | UPGRADABLE,
+                atomic::Ordering::Acquire,
+                atomic::Ordering::Relaxed,
+            ) {
+                Ok(_) => {
+                    self.record_readers((state & READERS) + 1);
+                    return true;
+                }
+                Err(actual) => {
+                    state = actual;
+                    backoff.spin();
+                }
+            }
         }
     }
 
-    /// Undo any previous mapping applied, returning the guard back to its original state.
-    pub fn unmap(guard: Self) -> WriteGuard<'lock, T> {
-        let guard = ManuallyDrop::new(guard);
-        unsafe { WriteGuard::new(guard.lock) }
+    /// Record that `readers` readers are holding the lock, for
+    /// [`peak_readers`](Self::peak_readers).
+    #[cfg_attr(not(feature = "stats"), allow(clippy::unused_self, unused_variables))]
+    fn record_readers(&self, readers: usize) {
+        #[cfg(feature = "stats")]
+        if readers > self.peak_readers.load(atomic::Ordering::Relaxed) {
+            self.peak_readers
+                .fetch_max(readers, atomic::Ordering::Relaxed);
+        }
+    }
+
+    /// Attempt to set the lock's state to write-locked.
+    fn lock_exclusive(&self) -> Result<(), TryLockError> {
+        // Only attempt the compare-exchange if the lock looks free, so that failing writers don't
+        // take the cache line away from the readers holding the lock.
+        let mut state = self.state.load(atomic::Ordering::Relaxed);
+        if state == 0 {
+            match self.state.compare_exchange(
+                0,
+                WRITER,
+                atomic::Ordering::Acquire,
+                atomic::Ordering::Relaxed,
+            ) {
+                Ok(_) => return Ok(()),
+                Err(actual) => state = actual,
+            }
+        }
+        Err(if state & WRITER != 0 {
+            TryLockError::WriteLocked
+        } else {
+            TryLockError::ReadLocked
+        })
+    }
+
+    /// Remove a reader from the lock's state. The caller must be giving up its read lock.
+    unsafe fn unlock_shared(&self) {
+        let state = self.state.fetch_sub(1, atomic::Ordering::Release);
+        debug_assert!(
+            state & WRITER == 0 && state & READERS != 0,
+            "read-unlocked a TryRwLock that was not read-locked",
+        );
+        self.reader_released(state, state - 1);
+    }
+
+    /// Remove the writer from the lock's state. The caller must be giving up its write lock.
+    unsafe fn unlock_exclusive(&self) {
+        // This can't simply store 0, as that would lose the increments of readers that are about
+        // to back out.
+        let state = self.state.fetch_sub(WRITER, atomic::Ordering::Release);
+        debug_assert!(
+            state & WRITER != 0 && state & UPGRADABLE == 0,
+            "write-unlocked a TryRwLock that was not write-locked",
+        );
+        self.wake_readers();
+        self.wake_writers();
+    }
+
+    /// Wake the tasks that may be able to lock the lock now that a reader has left it, given the
+    /// states before and after it left.
+    ///
+    /// Writers can only get in once there are no readers left, and waiting readers only need
+    /// waking if leaving made room in a full lock. Leaving any other readers behind wakes nothing.
+    fn reader_released(&self, before: usize, after: usize) {
+        if after == 0 {
+            self.wake_writers();
+        }
+        if after & WRITER == 0
+            && before & READERS >= Self::MAX_READERS
+            && after & READERS < Self::MAX_READERS
+        {
+            self.wake_readers();
+        }
+    }
+
+    /// Wake the tasks that may be able to lock the lock now that a reader that was not allowed in
+    /// has backed out, given the state it left behind.
+    ///
+    /// While it was counted in the state, the reader may have hidden a release from others: a
+    /// writer may have seen it instead of an unlocked lock, or the last reader to leave a full lock
+    /// may have seen it keep the lock full. So this wakes whoever could lock the lock now, but
+    /// never readers of a lock that is still full or write-locked, which would only fail again.
+    fn reader_backed_out(&self, state: usize) {
+        if state == 0 {
+            self.wake_writers();
+        }
+        if state & WRITER == 0 && state & READERS < Self::MAX_READERS {
+            self.wake_readers();
+        }
+    }
+
+    /// Wake any tasks waiting to read-lock the lock.
+    #[cfg_attr(not(feature = "async"), allow(clippy::unused_self))]
+    fn wake_readers(&self) {
+        #[cfg(feature = "async")]
+        {
+            // Either a task registering its waker will see the release on its next attempt, or this
+            // will see the waker. Pairs with the fence in `WakerList::register`.
+            atomic::fence(atomic::Ordering::SeqCst);
+            self.wakers.readers.wake_all();
+        }
+    }
+
+    /// Wake one of the tasks waiting to write-lock the lock.
+    ///
+    /// Only one writer can get in, so waking the others would only have them fail and register
+    /// again. A woken writer that fails leaves the lock to whoever beat it, whose own release will
+    /// wake the next writer.
+    #[cfg_attr(not(feature = "async"), allow(clippy::unused_self))]
+    fn wake_writers(&self) {
+        #[cfg(feature = "async")]
+        {
+            // See `wake_readers`.
+            atomic::fence(atomic::Ordering::SeqCst);
+            self.wakers.writers.wake_one();
+        }
+    }
+}
+
+/// Get the number of readers from a lock's state, which is 0 if it is write-locked.
+const fn reader_count(state: usize) -> usize {
+    if state & WRITER == 0 {
+        state & READERS
+    } else {
+        0
+    }
+}
+
+/// Call `f` until it returns `Some`, spinning with exponential backoff between attempts and
+/// retrying at most `spins` times.
+fn spin_for<R>(spins: usize, mut f: impl FnMut() -> Option<R>) -> Option<R> {
+    let mut backoff = Backoff::new();
+    for _ in 0..spins {
+        if let Some(value) = f() {
+            return Some(value);
+        }
+        backoff.spin();
+    }
+    f()
+}
+
+/// Call `f` until it returns `Some`, spinning with exponential backoff and then yielding between
+/// attempts.
+#[cfg(feature = "std")]
+fn spin_until<R>(mut f: impl FnMut() -> Option<R>) -> R {
+    let mut backoff = Backoff::new();
+    loop {
+        if let Some(value) = f() {
+            return value;
+        }
+        backoff.snooze();
+    }
+}
+
+impl<T: ?Sized + Debug, const MAX_READERS: usize> Debug for TryRwLock<T, MAX_READERS> {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        #[allow(clippy::option_if_let_else)]
+        if let Some(guard) = self.try_read() {
+            f.debug_struct("TryRwLock").field("data", &&*guard).finish()
+        } else {
+            struct LockedPlaceholder;
+            impl Debug for LockedPlaceholder {
+                fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+                    f.write_str("<locked>")
+                }
+            }
+
+            f.debug_struct("TryRwLock")
+                .field("data", &LockedPlaceholder)
+                .finish()
+        }
+    }
+}
+
+/// Formats the data if the lock can be read, or `<locked>` if it is currently locked for writing.
+impl<T: ?Sized + Display, const MAX_READERS: usize> Display for TryRwLock<T, MAX_READERS> {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        match self.try_read() {
+            Some(guard) => Display::fmt(&*guard, f),
+            None => f.write_str("<locked>"),
+        }
+    }
+}
+
+/// Cloning a `TryRwLock` briefly read-locks it and clones the data into a new, unlocked lock.
+///
+/// # Panics
+///
+/// Like `RefCell`, cloning panics if the lock is currently locked for writing.
+impl<T: Clone, const MAX_READERS: usize> Clone for TryRwLock<T, MAX_READERS> {
+    #[track_caller]
+    fn clone(&self) -> Self {
+        let guard = self
+            .try_read()
+            .expect("cannot clone a write-locked TryRwLock");
+        Self::with_max_readers(guard.clone())
+    }
+}
+
+/// Comparing two `TryRwLock`s briefly read-locks both of them and compares their data.
+///
+/// # Panics
+///
+/// Like `RefCell`, comparing panics if either lock is currently locked for writing.
+impl<T: ?Sized + PartialEq, const MAX_READERS: usize> PartialEq for TryRwLock<T, MAX_READERS> {
+    #[track_caller]
+    fn eq(&self, other: &Self) -> bool {
+        let this = self
+            .try_read()
+            .expect("cannot compare a write-locked TryRwLock");
+        let other = other
+            .try_read()
+            .expect("cannot compare a write-locked TryRwLock");
+        *this == *other
+    }
+}
+
+impl<T: ?Sized + Eq, const MAX_READERS: usize> Eq for TryRwLock<T, MAX_READERS> {}
+
+/// Hashing a `TryRwLock` briefly read-locks it and hashes its data, consistently with its
+/// [`PartialEq`] implementation.
+///
+/// # Panics
+///
+/// Like comparing, hashing panics if the lock is currently locked for writing.
+impl<T: ?Sized + Hash, const MAX_READERS: usize> Hash for TryRwLock<T, MAX_READERS> {
+    #[track_caller]
+    fn hash<H: Hasher>(&self, state: &mut H) {
+        self.try_read()
+            .expect("cannot hash a write-locked TryRwLock")
+            .hash(state);
+    }
+}
+
+impl<T> From<T> for TryRwLock<T> {
+    fn from(data: T) -> Self {
+        Self::new(data)
+    }
+}
+
+// This implementation requires `T` to be `Send` because it owns a `T`, allows unique access to it
+// and destroys it in its destructor.
+unsafe impl<T: ?Sized + Send, const MAX_READERS: usize> Send for TryRwLock<T, MAX_READERS> {}
+
+// `T` is required to be `Send` because this type allows upgrading `&TryRwLock<T>` to `&mut T`,
+// which means it can be dropped on a different thread. `T` is additionally required to be `Sync`
+// because it allows the user to obtain an `&T`.
+unsafe impl<T: ?Sized + Send + Sync, const MAX_READERS: usize> Sync for TryRwLock<T, MAX_READERS> {}
+
+// Guards release the lock in their destructors, which also run while unwinding, so a panic can
+// never leave the lock itself locked or its state inconsistent. Whether the `T` can be observed in
+// a broken state after a panic is up to `T`, hence the bounds; there is no poisoning.
+impl<T: ?Sized + UnwindSafe, const MAX_READERS: usize> UnwindSafe for TryRwLock<T, MAX_READERS> {}
+impl<T: ?Sized + RefUnwindSafe, const MAX_READERS: usize> RefUnwindSafe
+    for TryRwLock<T, MAX_READERS>
+{
+}
+
+/// The reason a `TryRwLock` could not be locked.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+#[non_exhaustive]
+pub enum TryLockError {
+    /// The lock is currently being written to.
+    WriteLocked,
+    /// The lock is currently being read from.
+    ReadLocked,
+    /// The lock already has the maximum number of readers.
+    ReaderSaturated,
+}
+
+impl Display for TryLockError {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        f.write_str(match self {
+            Self::WriteLocked => "lock is write-locked",
+            Self::ReadLocked => "lock is read-locked",
+            Self::ReaderSaturated => "lock has the maximum number of readers",
+        })
     }
 }
 
-impl<T, U> Deref for WriteGuard<'_, T, U> {
-    type Target = U;
+impl ::core::error::Error for TryLockError {}
+
+/// A RAII guard that guarantees shared read access to a `TryRwLock`.
+///
+/// # Examples
+///
+/// The guard borrows its lock, so it cannot outlive it:
+///
+/// ```compile_fail
+/// use try_rwlock::{ReadGuard, TryRwLock};
+///
+/// let guard: ReadGuard<'_, i32> = {
+///     let lock = TryRwLock::new(0);
+///     lock.try_read().unwrap()
+/// };
+/// ```
+///
+/// It can only be sent to another thread if the lock's data could be shared with it, so a guard of
+/// a `Cell` stays on its thread:
+///
+/// ```compile_fail
+/// use std::cell::Cell;
+/// use try_rwlock::TryRwLock;
+///
+/// let lock = TryRwLock::new(Cell::new(0));
+/// let guard = lock.try_read().unwrap();
+/// std::thread::scope(|s| {
+///     s.spawn(move || guard.set(1));
+/// });
+/// ```
+///
+/// A failed [`try_upgrade`](Self::try_upgrade) hands the guard back, still locked:
+///
+/// ```
+/// use try_rwlock::{ReadGuard, TryRwLock};
+///
+/// let lock = TryRwLock::new(0);
+/// let guard = lock.try_read().unwrap();
+/// let other = lock.try_read().unwrap();
+/// let guard = ReadGuard::try_upgrade(guard).unwrap_err();
+/// assert_eq!(*guard, 0);
+/// assert_eq!(lock.reader_count(), 2);
+/// # drop((guard, other));
+/// ```
+#[must_use = "if unused the TryRwLock will immediately unlock"]
+pub struct ReadGuard<
+    'lock,
+    T: ?Sized,
+    U: ?Sized = T,
+    const MAX_READERS: usize = DEFAULT_MAX_READERS,
+> {
+    data: NonNull<U>,
+    lock: &'lock TryRwLock<T, MAX_READERS>,
+    // This also enforces a `U: 'lock` bound, which is necessary for soundness (see
+    // https://github.com/SabrinaJewson/try-rwlock.rs/issues/2)
+    _covariant_over_u: PhantomData<&'lock U>,
+}
+
+// As we expose a `TryRwlock<T>`, we first inherit the bounds for `&TryRwLock<T>: Sync` on T; as we
+// give shared access to the `U`, we use the same bounds as `&U` on `U`.
+unsafe impl<T: ?Sized + Send + Sync, U: ?Sized + Sync, const MAX_READERS: usize> Send
+    for ReadGuard<'_, T, U, MAX_READERS>
+{
+}
+unsafe impl<T: ?Sized + Send + Sync, U: ?Sized + Sync, const MAX_READERS: usize> Sync
+    for ReadGuard<'_, T, U, MAX_READERS>
+{
+}
+
+#[allow(clippy::mismatching_type_param_order)]
+impl<'lock, T: ?Sized, const MAX_READERS: usize> ReadGuard<'lock, T, T, MAX_READERS> {
+    unsafe fn new(lock: &'lock TryRwLock<T, MAX_READERS>) -> Self {
+        Self {
+            data: NonNull::new(lock.data.get()).expect("`UnsafeCell::get` never returns null"),
+            lock,
+            _covariant_over_u: PhantomData,
+        }
+    }
+
+    /// Consume the `ReadGuard` without unlocking the lock, returning a pointer to the lock.
+    ///
+    /// The lock stays read-locked until the guard is rebuilt with [`from_raw`](Self::from_raw)
+    /// and dropped. If it is never rebuilt, the lock can never be write-locked again.
+    #[must_use = "the lock stays read-locked unless the guard is rebuilt with `from_raw`"]
+    pub fn into_raw(guard: Self) -> *const TryRwLock<T, MAX_READERS> {
+        ManuallyDrop::new(guard).lock
+    }
+
+    /// Release the read lock and immediately attempt to take it again, giving a waiting writer a
+    /// chance to get in.
+    ///
+    /// This is useful when holding a guard across iterations of a loop. If a writer takes the
+    /// lock in between, the guard cannot be restored and this function will return `None`; the
+    /// data may also have changed even if it returns `Some`.
+    #[must_use = "if unused the TryRwLock will immediately unlock"]
+    pub fn bump(guard: Self) -> Option<Self> {
+        let lock = guard.lock;
+        drop(guard);
+        lock.try_read()
+    }
+
+    /// Rebuild a `ReadGuard` from a pointer returned by [`into_raw`](Self::into_raw).
+    ///
+    /// # Safety
+    ///
+    /// `ptr` must have come from `into_raw` on a `ReadGuard` of the same lock, and each call to
+    /// `into_raw` may only be matched with one call to `from_raw`. The lock must remain valid for
+    /// `'lock`.
+    pub unsafe fn from_raw(ptr: *const TryRwLock<T, MAX_READERS>) -> Self {
+        unsafe { Self::new(&*ptr) }
+    }
+}
+
+impl<'lock, T: ?Sized, U: ?Sized, const MAX_READERS: usize> ReadGuard<'lock, T, U, MAX_READERS> {
+    /// Get a shared reference to the lock that this read guard has locked.
+    #[must_use]
+    pub fn rwlock(guard: &Self) -> &'lock TryRwLock<T, MAX_READERS> {
+        guard.lock
+    }
+
+    /// Check whether two read guards were locked from the same `TryRwLock`, regardless of what
+    /// they have been mapped to.
+    #[must_use]
+    pub fn ptr_eq<V: ?Sized>(a: &Self, b: &ReadGuard<'_, T, V, MAX_READERS>) -> bool {
+        TryRwLock::ref_eq(a.lock, b.lock)
+    }
+
+    /// Get a raw pointer to the data this guard has locked.
+    ///
+    /// The pointer may be read from while the guard is alive, but must not be written to.
+    #[must_use]
+    pub fn as_ptr(guard: &Self) -> *const U {
+        guard.data.as_ptr()
+    }
+
+    /// Create another read guard over the same data, without going through
+    /// [`TryRwLock::try_read`].
+    ///
+    /// Both guards hold their own read lock and release it independently. This function will
+    /// return `None` only if the lock already has the maximum number of readers.
+    #[must_use]
+    pub fn clone_guard(guard: &Self) -> Option<Self> {
+        guard.lock.lock_shared().ok().map(|()| Self {
+            data: guard.data,
+            lock: guard.lock,
+            _covariant_over_u: PhantomData,
+        })
+    }
+
+    /// Attempt to upgrade the `ReadGuard` to a `WriteGuard`.
+    ///
+    /// Note that for soundness reasons, this will undo any [`map`](Self::map)ping that has
+    /// previously been applied.
+    ///
+    /// # Errors
+    ///
+    /// Fails if there is more than one reader currently using the lock.
+    pub fn try_upgrade(guard: Self) -> Result<WriteGuard<'lock, T, T, MAX_READERS>, Self> {
+        match guard.lock.state.compare_exchange(
+            1,
+            WRITER,
+            atomic::Ordering::Acquire,
+            atomic::Ordering::Relaxed,
+        ) {
+            Ok(_) => {
+                let guard = ManuallyDrop::new(guard);
+                Ok(unsafe { WriteGuard::new(guard.lock) })
+            }
+            Err(state) => {
+                debug_assert!(
+                    state & WRITER == 0 && state & READERS > 1,
+                    "upgrading a reader of a TryRwLock that was not read-locked",
+                );
+                Err(guard)
+            }
+        }
+    }
+
+    /// Attempt to upgrade the `ReadGuard` to a `WriteGuard`, retrying up to `spins` times if other
+    /// readers are using the lock.
+    ///
+    /// This is like [`try_upgrade`](Self::try_upgrade), but gives other readers a short window to
+    /// release the lock, backing off a little longer after each failed attempt. It gives up after
+    /// `spins` retries.
+    ///
+    /// # Errors
+    ///
+    /// Fails if there is still more than one reader using the lock after every attempt.
+    pub fn upgrade_spin(
+        guard: Self,
+        spins: usize,
+    ) -> Result<WriteGuard<'lock, T, T, MAX_READERS>, Self> {
+        let mut guard = guard;
+        let mut backoff = Backoff::new();
+        for _ in 0..spins {
+            match Self::try_upgrade(guard) {
+                Ok(writer) => return Ok(writer),
+                Err(reader) => guard = reader,
+            }
+            backoff.spin();
+        }
+        Self::try_upgrade(guard)
+    }
+
+    /// Attempt to upgrade the `ReadGuard` to a `WriteGuard` and [map](WriteGuard::map) it to a
+    /// part of the data in one step.
+    ///
+    /// Like [`try_upgrade`](Self::try_upgrade), this undoes any mapping that has previously been
+    /// applied, so the closure receives the whole of the lock's data. The lock stays held
+    /// throughout, so no other writer can get in between the upgrade and the closure.
+    ///
+    /// # Errors
+    ///
+    /// Fails if there is more than one reader currently using the lock, in which case the closure
+    /// is not called and the original guard is returned.
+    pub fn try_upgrade_map<V: ?Sized>(
+        guard: Self,
+        f: impl FnOnce(&mut T) -> &mut V,
+    ) -> Result<WriteGuard<'lock, T, V, MAX_READERS>, Self> {
+        Self::try_upgrade(guard).map(|writer| WriteGuard::map(writer, f))
+    }
+
+    /// Map to another value and keep locked.
+    pub fn map<V: ?Sized>(
+        guard: Self,
+        f: impl FnOnce(&U) -> &V,
+    ) -> ReadGuard<'lock, T, V, MAX_READERS> {
+        let guard = ManuallyDrop::new(guard);
+        ReadGuard {
+            data: NonNull::from(f(&**guard)),
+            lock: guard.lock,
+            _covariant_over_u: PhantomData,
+        }
+    }
+
+    /// Attempt to map to another value and keep locked.
+    ///
+    /// # Errors
+    ///
+    /// If the closure returns `None`, the original guard is returned unchanged.
+    pub fn try_map<V: ?Sized>(
+        guard: Self,
+        f: impl FnOnce(&U) -> Option<&V>,
+    ) -> Result<ReadGuard<'lock, T, V, MAX_READERS>, Self> {
+        let data = match f(&*guard) {
+            Some(data) => NonNull::from(data),
+            None => return Err(guard),
+        };
+        let guard = ManuallyDrop::new(guard);
+        Ok(ReadGuard {
+            data,
+            lock: guard.lock,
+            _covariant_over_u: PhantomData,
+        })
+    }
+
+    /// Undo any previous mapping applied, returning the guard back to its original state.
+    pub fn unmap(guard: Self) -> ReadGuard<'lock, T, T, MAX_READERS> {
+        let guard = ManuallyDrop::new(guard);
+        unsafe { ReadGuard::new(guard.lock) }
+    }
+
+    /// Leak the guard, returning a reference to the data that lasts as long as the lock.
+    ///
+    /// The read lock is never released, so the lock can never be written to again.
+    #[must_use]
+    pub fn leak(guard: Self) -> &'lock U {
+        let guard = ManuallyDrop::new(guard);
+        unsafe { guard.data.as_ref() }
+    }
+}
+
+impl<T: ?Sized, U: ?Sized, const MAX_READERS: usize> Deref for ReadGuard<'_, T, U, MAX_READERS> {
+    type Target = U;
+
+    fn deref(&self) -> &Self::Target {
+        unsafe { self.data.as_ref() }
+    }
+}
+
+impl<T: ?Sized, U: ?Sized, const MAX_READERS: usize> AsRef<U> for ReadGuard<'_, T, U, MAX_READERS> {
+    fn as_ref(&self) -> &U {
+        self
+    }
+}
+
+impl<T: ?Sized, U: ?Sized, const MAX_READERS: usize> Borrow<U>
+    for ReadGuard<'_, T, U, MAX_READERS>
+{
+    fn borrow(&self) -> &U {
+        self
+    }
+}
+
+#[allow(clippy::mismatching_type_param_order)]
+impl<'lock, T: ?Sized, const MAX_READERS: usize> TryFrom<&'lock TryRwLock<T, MAX_READERS>>
+    for ReadGuard<'lock, T, T, MAX_READERS>
+{
+    type Error = TryLockError;
+
+    /// Attempt to read-lock the lock, as in [`TryRwLock::try_read_err`].
+    fn try_from(lock: &'lock TryRwLock<T, MAX_READERS>) -> Result<Self, Self::Error> {
+        lock.try_read_err()
+    }
+}
+
+impl<T: ?Sized, U: ?Sized, const MAX_READERS: usize> Drop for ReadGuard<'_, T, U, MAX_READERS> {
+    fn drop(&mut self) {
+        unsafe { self.lock.unlock_shared() };
+    }
+}
+
+impl<T: ?Sized, U: ?Sized + Debug, const MAX_READERS: usize> Debug
+    for ReadGuard<'_, T, U, MAX_READERS>
+{
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        f.debug_struct("TryRwLockReadGuard")
+            .field("data", &&**self)
+            .finish()
+    }
+}
+
+impl<T: ?Sized, U: ?Sized + Display, const MAX_READERS: usize> Display
+    for ReadGuard<'_, T, U, MAX_READERS>
+{
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        Display::fmt(&**self, f)
+    }
+}
+
+/// A RAII guard that guarantees unique write access to a `TryRwLock`.
+#[must_use = "if unused the TryRwLock will immediately unlock"]
+pub struct WriteGuard<
+    'lock,
+    T: ?Sized,
+    U: ?Sized = T,
+    const MAX_READERS: usize = DEFAULT_MAX_READERS,
+> {
+    data: NonNull<U>,
+    lock: &'lock TryRwLock<T, MAX_READERS>,
+    _invariant_over_u: PhantomData<&'lock mut U>,
+}
+
+// As we expose a `TryRwlock<T>`, we first inherit the bounds for `&TryRwLock<T>: Sync` on T; as we
+// give exclusive access to the `U`, we use the same bounds as `&mut U` on `U`.
+unsafe impl<T: ?Sized + Send + Sync, U: ?Sized + Send, const MAX_READERS: usize> Send
+    for WriteGuard<'_, T, U, MAX_READERS>
+{
+}
+unsafe impl<T: ?Sized + Send + Sync, U: ?Sized + Sync, const MAX_READERS: usize> Sync
+    for WriteGuard<'_, T, U, MAX_READERS>
+{
+}
+
+#[allow(clippy::mismatching_type_param_order)]
+impl<'lock, T: ?Sized, const MAX_READERS: usize> WriteGuard<'lock, T, T, MAX_READERS> {
+    unsafe fn new(lock: &'lock TryRwLock<T, MAX_READERS>) -> Self {
+        Self {
+            data: NonNull::new(lock.data.get()).expect("`UnsafeCell::get` never returns null"),
+            lock,
+            _invariant_over_u: PhantomData,
+        }
+    }
+
+    /// Consume the `WriteGuard` without unlocking the lock, returning a pointer to the lock.
+    ///
+    /// The lock stays write-locked until the guard is rebuilt with [`from_raw`](Self::from_raw)
+    /// and dropped. If it is never rebuilt, the lock can never be locked again.
+    #[must_use = "the lock stays write-locked unless the guard is rebuilt with `from_raw`"]
+    pub fn into_raw(guard: Self) -> *const TryRwLock<T, MAX_READERS> {
+        ManuallyDrop::new(guard).lock
+    }
+
+    /// Rebuild a `WriteGuard` from a pointer returned by [`into_raw`](Self::into_raw).
+    ///
+    /// # Safety
+    ///
+    /// `ptr` must have come from `into_raw` on a `WriteGuard` of the same lock, and each call to
+    /// `into_raw` may only be matched with one call to `from_raw`. The lock must remain valid for
+    /// `'lock`.
+    pub unsafe fn from_raw(ptr: *const TryRwLock<T, MAX_READERS>) -> Self {
+        unsafe { Self::new(&*ptr) }
+    }
+}
+
+impl<'lock, T: ?Sized, U: ?Sized, const MAX_READERS: usize> WriteGuard<'lock, T, U, MAX_READERS> {
+    /// Get a shared reference to the lock that this write guard has locked.
+    #[must_use]
+    pub fn rwlock(guard: &Self) -> &'lock TryRwLock<T, MAX_READERS> {
+        guard.lock
+    }
+
+    /// Get a raw pointer to the data this guard has locked.
+    ///
+    /// The pointer may be read from and written to while the guard is alive and not otherwise
+    /// used.
+    #[must_use]
+    pub fn as_mut_ptr(guard: &mut Self) -> *mut U {
+        guard.data.as_ptr()
+    }
+
+    /// Downgrade the `WriteGuard` to a `ReadGuard`.
+    ///
+    /// Note that for soundness reasons, this will undo any [`map`](Self::map)ing that has
+    /// previously been applied.
+    pub fn downgrade(guard: Self) -> ReadGuard<'lock, T, T, MAX_READERS> {
+        let guard = ManuallyDrop::new(guard);
+        // Atomically replace the writer with a single reader, keeping any backing-out readers.
+        let state = guard
+            .lock
+            .state
+            .fetch_sub(WRITER - 1, atomic::Ordering::Release);
+        debug_assert!(
+            state & WRITER != 0,
+            "downgraded a TryRwLock that was not write-locked",
+        );
+        guard.lock.record_readers(1);
+        guard.lock.wake_readers();
+        unsafe { ReadGuard::new(guard.lock) }
+    }
+
+    /// Downgrade the `WriteGuard` to a `ReadGuard` of part of its data.
+    ///
+    /// `f` is called while the lock is still write-locked, and the lock then goes straight to
+    /// being read-locked, so no other writer can get in between.
+    pub fn downgrade_map<V: ?Sized>(
+        guard: Self,
+        f: impl FnOnce(&U) -> &V,
+    ) -> ReadGuard<'lock, T, V, MAX_READERS> {
+        let data = NonNull::from(f(&guard));
+        let guard = ManuallyDrop::new(Self::downgrade(guard));
+        ReadGuard {
+            data,
+            lock: guard.lock,
+            _covariant_over_u: PhantomData,
+        }
+    }
+
+    /// Downgrade the `WriteGuard` to an `UpgradableReadGuard`.
+    ///
+    /// This lets other readers in while keeping the right to upgrade back to a writer. No other
+    /// writer can acquire the lock in between.
+    ///
+    /// Note that for soundness reasons, this will undo any [`map`](Self::map)ing that has
+    /// previously been applied.
+    pub fn downgrade_to_upgradable(guard: Self) -> UpgradableReadGuard<'lock, T, MAX_READERS> {
+        let guard = ManuallyDrop::new(guard);
+        let state = guard
+            .lock
+            .state
+            .fetch_sub(WRITER - (UPGRADABLE | 1), atomic::Ordering::Release);
+        debug_assert!(
+            state & WRITER != 0 && state & UPGRADABLE == 0,
+            "downgraded a TryRwLock that was not write-locked",
+        );
+        guard.lock.record_readers(1);
+        guard.lock.wake_readers();
+        UpgradableReadGuard { lock: guard.lock }
+    }
+
+    /// Map to another value and keep locked.
+    pub fn map<V: ?Sized>(
+        guard: Self,
+        f: impl FnOnce(&mut U) -> &mut V,
+    ) -> WriteGuard<'lock, T, V, MAX_READERS> {
+        let mut guard = ManuallyDrop::new(guard);
+        WriteGuard {
+            data: NonNull::from(f(&mut **guard)),
+            lock: guard.lock,
+            _invariant_over_u: PhantomData,
+        }
+    }
+
+    /// Attempt to map to another value and keep locked.
+    ///
+    /// # Errors
+    ///
+    /// If the closure returns `None`, the original guard is returned unchanged.
+    pub fn try_map<V: ?Sized>(
+        mut guard: Self,
+        f: impl FnOnce(&mut U) -> Option<&mut V>,
+    ) -> Result<WriteGuard<'lock, T, V, MAX_READERS>, Self> {
+        let data = match f(&mut *guard) {
+            Some(data) => NonNull::from(data),
+            None => return Err(guard),
+        };
+        let guard = ManuallyDrop::new(guard);
+        Ok(WriteGuard {
+            data,
+            lock: guard.lock,
+            _invariant_over_u: PhantomData,
+        })
+    }
+
+    /// Split the guard into two guards over disjoint parts of the data, such as two fields of a
+    /// struct.
+    ///
+    /// The write lock is only released once both of the returned guards have been dropped.
+    pub fn map_split<A: ?Sized, B: ?Sized>(
+        mut guard: Self,
+        f: impl FnOnce(&mut U) -> (&mut A, &mut B),
+    ) -> (
+        SplitWriteGuard<'lock, T, A, MAX_READERS>,
+        SplitWriteGuard<'lock, T, B, MAX_READERS>,
+    ) {
+        let (a, b) = f(&mut *guard);
+        let (a, b) = (NonNull::from(a), NonNull::from(b));
+        let guard = ManuallyDrop::new(guard);
+        // We hold the write lock, so nothing else can be using this bit.
+        guard
+            .lock
+            .state
+            .fetch_or(UPGRADABLE, atomic::Ordering::Relaxed);
+        (
+            SplitWriteGuard {
+                data: a,
+                lock: guard.lock,
+                _invariant_over_u: PhantomData,
+            },
+            SplitWriteGuard {
+                data: b,
+                lock: guard.lock,
+                _invariant_over_u: PhantomData,
+            },
+        )
+    }
+
+    /// Undo any previous mapping applied, returning the guard back to its original state.
+    pub fn unmap(guard: Self) -> WriteGuard<'lock, T, T, MAX_READERS> {
+        let guard = ManuallyDrop::new(guard);
+        unsafe { WriteGuard::new(guard.lock) }
+    }
+
+    /// Leak the guard, returning a mutable reference to the data that lasts as long as the lock.
+    ///
+    /// The write lock is never released, so the lock can never be read from or written to again.
+    #[must_use]
+    pub fn leak(guard: Self) -> &'lock mut U {
+        let mut guard = ManuallyDrop::new(guard);
+        unsafe { guard.data.as_mut() }
+    }
+}
+
+impl<T: ?Sized, U: ?Sized, const MAX_READERS: usize> Deref for WriteGuard<'_, T, U, MAX_READERS> {
+    type Target = U;
+
+    fn deref(&self) -> &Self::Target {
+        unsafe { self.data.as_ref() }
+    }
+}
+impl<T: ?Sized, U: ?Sized, const MAX_READERS: usize> DerefMut
+    for WriteGuard<'_, T, U, MAX_READERS>
+{
+    fn deref_mut(&mut self) -> &mut Self::Target {
+        unsafe { self.data.as_mut() }
+    }
+}
+
+impl<T: ?Sized, U: ?Sized, const MAX_READERS: usize> AsRef<U>
+    for WriteGuard<'_, T, U, MAX_READERS>
+{
+    fn as_ref(&self) -> &U {
+        self
+    }
+}
+impl<T: ?Sized, U: ?Sized, const MAX_READERS: usize> AsMut<U>
+    for WriteGuard<'_, T, U, MAX_READERS>
+{
+    fn as_mut(&mut self) -> &mut U {
+        self
+    }
+}
+
+impl<T: ?Sized, U: ?Sized, const MAX_READERS: usize> Borrow<U>
+    for WriteGuard<'_, T, U, MAX_READERS>
+{
+    fn borrow(&self) -> &U {
+        self
+    }
+}
+impl<T: ?Sized, U: ?Sized, const MAX_READERS: usize> BorrowMut<U>
+    for WriteGuard<'_, T, U, MAX_READERS>
+{
+    fn borrow_mut(&mut self) -> &mut U {
+        self
+    }
+}
+
+#[allow(clippy::mismatching_type_param_order)]
+impl<'lock, T: ?Sized, const MAX_READERS: usize> TryFrom<&'lock TryRwLock<T, MAX_READERS>>
+    for WriteGuard<'lock, T, T, MAX_READERS>
+{
+    type Error = TryLockError;
+
+    /// Attempt to write-lock the lock, as in [`TryRwLock::try_write_err`].
+    fn try_from(lock: &'lock TryRwLock<T, MAX_READERS>) -> Result<Self, Self::Error> {
+        lock.try_write_err()
+    }
+}
+
+impl<T: ?Sized, U: ?Sized, const MAX_READERS: usize> Drop for WriteGuard<'_, T, U, MAX_READERS> {
+    fn drop(&mut self) {
+        unsafe { self.lock.unlock_exclusive() };
+    }
+}
+
+impl<T: ?Sized, U: ?Sized + Debug, const MAX_READERS: usize> Debug
+    for WriteGuard<'_, T, U, MAX_READERS>
+{
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        f.debug_struct("TryRwLockWriteGuard")
+            .field("data", &&**self)
+            .finish()
+    }
+}
+
+impl<T: ?Sized, U: ?Sized + Display, const MAX_READERS: usize> Display
+    for WriteGuard<'_, T, U, MAX_READERS>
+{
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        Display::fmt(&**self, f)
+    }
+}
+
+/// One of the two halves of a [`WriteGuard`] that has been split with
+/// [`WriteGuard::map_split`].
+///
+/// This gives unique access to its part of the data, but unlike a `WriteGuard` it cannot be
+/// unmapped or downgraded, since the other half may still be in use. The lock is released when
+/// both halves have been dropped.
+#[must_use = "if unused this half of the write lock will immediately be released"]
+pub struct SplitWriteGuard<
+    'lock,
+    T: ?Sized,
+    U: ?Sized,
+    const MAX_READERS: usize = DEFAULT_MAX_READERS,
+> {
+    data: NonNull<U>,
+    lock: &'lock TryRwLock<T, MAX_READERS>,
+    _invariant_over_u: PhantomData<&'lock mut U>,
+}
+
+// These use the same bounds as `WriteGuard`.
+unsafe impl<T: ?Sized + Send + Sync, U: ?Sized + Send, const MAX_READERS: usize> Send
+    for SplitWriteGuard<'_, T, U, MAX_READERS>
+{
+}
+unsafe impl<T: ?Sized + Send + Sync, U: ?Sized + Sync, const MAX_READERS: usize> Sync
+    for SplitWriteGuard<'_, T, U, MAX_READERS>
+{
+}
+
+impl<'lock, T: ?Sized, U: ?Sized, const MAX_READERS: usize>
+    SplitWriteGuard<'lock, T, U, MAX_READERS>
+{
+    /// Get a shared reference to the lock that this guard has locked.
+    #[must_use]
+    pub fn rwlock(guard: &Self) -> &'lock TryRwLock<T, MAX_READERS> {
+        guard.lock
+    }
+
+    /// Map to another value and keep locked.
+    pub fn map<V: ?Sized>(
+        guard: Self,
+        f: impl FnOnce(&mut U) -> &mut V,
+    ) -> SplitWriteGuard<'lock, T, V, MAX_READERS> {
+        let mut guard = ManuallyDrop::new(guard);
+        SplitWriteGuard {
+            data: NonNull::from(f(&mut **guard)),
+            lock: guard.lock,
+            _invariant_over_u: PhantomData,
+        }
+    }
+}
+
+impl<T: ?Sized, U: ?Sized, const MAX_READERS: usize> Deref
+    for SplitWriteGuard<'_, T, U, MAX_READERS>
+{
+    type Target = U;
+
+    fn deref(&self) -> &Self::Target {
+        unsafe { self.data.as_ref() }
+    }
+}
+impl<T: ?Sized, U: ?Sized, const MAX_READERS: usize> DerefMut
+    for SplitWriteGuard<'_, T, U, MAX_READERS>
+{
+    fn deref_mut(&mut self) -> &mut Self::Target {
+        unsafe { self.data.as_mut() }
+    }
+}
+
+impl<T: ?Sized, U: ?Sized, const MAX_READERS: usize> Drop
+    for SplitWriteGuard<'_, T, U, MAX_READERS>
+{
+    fn drop(&mut self) {
+        // The first half to be dropped clears the split bit, and the second releases the lock.
+        // This acquires the other half's release so that its writes are published along with ours.
+        let state = self
+            .lock
+            .state
+            .fetch_and(!UPGRADABLE, atomic::Ordering::AcqRel);
+        debug_assert!(
+            state & WRITER != 0,
+            "dropped a split write guard of a TryRwLock that was not write-locked",
+        );
+        if state & UPGRADABLE == 0 {
+            unsafe { self.lock.unlock_exclusive() };
+        }
+    }
+}
+
+impl<T: ?Sized, U: ?Sized + Debug, const MAX_READERS: usize> Debug
+    for SplitWriteGuard<'_, T, U, MAX_READERS>
+{
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        f.debug_struct("TryRwLockSplitWriteGuard")
+            .field("data", &&**self)
+            .finish()
+    }
+}
+
+impl<T: ?Sized, U: ?Sized + Display, const MAX_READERS: usize> Display
+    for SplitWriteGuard<'_, T, U, MAX_READERS>
+{
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        Display::fmt(&**self, f)
+    }
+}
+
+/// A RAII guard that guarantees upgradable read access to a `TryRwLock`.
+///
+/// This gives shared access to the data like a [`ReadGuard`], but only one upgradable guard can
+/// exist at once and ordinary readers cannot upgrade while it is held.
+#[must_use = "if unused the TryRwLock will immediately unlock"]
+pub struct UpgradableReadGuard<'lock, T: ?Sized, const MAX_READERS: usize = DEFAULT_MAX_READERS> {
+    lock: &'lock TryRwLock<T, MAX_READERS>,
+}
+
+impl<'lock, T: ?Sized, const MAX_READERS: usize> UpgradableReadGuard<'lock, T, MAX_READERS> {
+    /// Get a shared reference to the lock that this upgradable read guard has locked.
+    #[must_use]
+    pub fn rwlock(guard: &Self) -> &'lock TryRwLock<T, MAX_READERS> {
+        guard.lock
+    }
+
+    /// Attempt to upgrade the `UpgradableReadGuard` to a `WriteGuard`.
+    ///
+    /// # Errors
+    ///
+    /// Fails if there are any ordinary readers currently using the lock.
+    pub fn try_upgrade(guard: Self) -> Result<WriteGuard<'lock, T, T, MAX_READERS>, Self> {
+        match guard.lock.state.compare_exchange(
+            UPGRADABLE | 1,
+            WRITER,
+            atomic::Ordering::Acquire,
+            atomic::Ordering::Relaxed,
+        ) {
+            Ok(_) => {
+                let guard = ManuallyDrop::new(guard);
+                Ok(unsafe { WriteGuard::new(guard.lock) })
+            }
+            Err(state) => {
+                debug_assert!(
+                    state & WRITER == 0 && state & UPGRADABLE != 0 && state & READERS > 1,
+                    "upgrading a TryRwLock that was not upgradably read-locked",
+                );
+                Err(guard)
+            }
+        }
+    }
+
+    /// Downgrade the `UpgradableReadGuard` to an ordinary `ReadGuard`, allowing another upgradable
+    /// reader to lock the lock.
+    pub fn downgrade(guard: Self) -> ReadGuard<'lock, T, T, MAX_READERS> {
+        let guard = ManuallyDrop::new(guard);
+        let state = guard
+            .lock
+            .state
+            .fetch_and(!UPGRADABLE, atomic::Ordering::Release);
+        debug_assert!(
+            state & WRITER == 0 && state & UPGRADABLE != 0,
+            "downgraded a TryRwLock that was not upgradably read-locked",
+        );
+        unsafe { ReadGuard::new(guard.lock) }
+    }
+}
+
+impl<T: ?Sized, const MAX_READERS: usize> Deref for UpgradableReadGuard<'_, T, MAX_READERS> {
+    type Target = T;
+
+    fn deref(&self) -> &Self::Target {
+        unsafe { &*self.lock.data.get() }
+    }
+}
+
+impl<T: ?Sized, const MAX_READERS: usize> Drop for UpgradableReadGuard<'_, T, MAX_READERS> {
+    fn drop(&mut self) {
+        let state = self
+            .lock
+            .state
+            .fetch_sub(UPGRADABLE | 1, atomic::Ordering::Release);
+        debug_assert!(
+            state & WRITER == 0 && state & UPGRADABLE != 0 && state & READERS != 0,
+            "upgradably read-unlocked a TryRwLock that was not upgradably read-locked",
+        );
+        self.lock
+            .reader_released(state - UPGRADABLE, state - (UPGRADABLE | 1));
+    }
+}
+
+impl<T: ?Sized + Debug, const MAX_READERS: usize> Debug
+    for UpgradableReadGuard<'_, T, MAX_READERS>
+{
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        f.debug_struct("TryRwLockUpgradableReadGuard")
+            .field("data", &&**self)
+            .finish()
+    }
+}
+
+impl<T: ?Sized + Display, const MAX_READERS: usize> Display
+    for UpgradableReadGuard<'_, T, MAX_READERS>
+{
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        Display::fmt(&**self, f)
+    }
+}
+
+#[test]
+fn test_new_locked() {
+    static LOCK: TryRwLock<u32> = TryRwLock::new_locked(0);
+    assert!(LOCK.is_write_locked());
+    assert!(LOCK.try_read().is_none());
+    assert!(LOCK.try_write().is_none());
+
+    std::thread::scope(|s| {
+        s.spawn(|| {
+            unsafe { *LOCK.data_ptr() = 5 };
+            unsafe { LOCK.force_unlock() };
+        });
+    });
+    assert!(!LOCK.is_locked());
+    assert_eq!(*LOCK.try_read().unwrap(), 5);
+}
+
+#[test]
+fn test_force_unlock() {
+    let lock = TryRwLock::new(1);
+
+    mem::forget(lock.try_read().unwrap());
+    mem::forget(lock.try_read().unwrap());
+    assert_eq!(lock.reader_count(), 2);
+    unsafe { lock.force_unlock_read() };
+    assert_eq!(lock.reader_count(), 1);
+    unsafe { lock.force_unlock_read() };
+    assert!(!lock.is_locked());
+
+    *WriteGuard::leak(lock.try_write().unwrap()) = 2;
+    assert!(lock.try_read().is_none());
+    unsafe { lock.force_unlock_write() };
+    assert!(!lock.is_locked());
+    assert_eq!(*lock.try_read().unwrap(), 2);
+}
+
+#[test]
+fn test_from_mut() {
+    fn increment_all(locks: &[&TryRwLock<&mut [u32]>]) {
+        for lock in locks {
+            for n in lock.try_write().unwrap().iter_mut() {
+                *n += 1;
+            }
+        }
+    }
+
+    let mut data = [1, 2, 3];
+    let lock = TryRwLock::from_mut(&mut data[..]);
+    increment_all(&[&lock]);
+    assert_eq!(lock.try_read().unwrap().len(), 3);
+    assert_eq!(lock.into_inner(), [2, 3, 4]);
+    assert_eq!(data, [2, 3, 4]);
+}
+
+#[test]
+fn test_static() {
+    static LOCK: TryRwLock<u32> = TryRwLock::new(0);
+
+    *LOCK.try_write().unwrap() += 1;
+    let thread = std::thread::spawn(|| *LOCK.try_read().unwrap());
+    assert_eq!(thread.join().unwrap(), 1);
+    assert!(!LOCK.is_locked());
+}
+
+#[test]
+fn test_static_array() {
+    static LOCKS: [TryRwLock<u32>; 4] = [const { TryRwLock::new(0) }; 4];
+
+    for (i, lock) in LOCKS.iter().enumerate() {
+        *lock.try_write().unwrap() = u32::try_from(i).unwrap();
+    }
+    let reader = LOCKS[1].try_read().unwrap();
+    assert!(LOCKS[1].try_write().is_none());
+    assert!(LOCKS[2].try_write().is_some());
+    drop(reader);
+    assert_eq!(
+        LOCKS
+            .iter()
+            .map(|lock| *lock.try_read().unwrap())
+            .sum::<u32>(),
+        6
+    );
+}
+
+#[test]
+fn test_read() {
+    let lock = TryRwLock::new("Hello World!".to_owned());
+    assert!(!lock.is_locked());
+    assert!(!lock.is_write_locked());
+
+    let guard_1 = lock.try_read().unwrap();
+    let guard_2 = lock.try_read().unwrap();
+
+    assert_eq!(&*guard_1, "Hello World!");
+    assert_eq!(&*guard_2, "Hello World!");
+
+    assert!(lock.try_write().is_none());
+    assert!(lock.is_locked());
+    assert!(!lock.is_write_locked());
+    let guard_1 = ReadGuard::try_upgrade(guard_1).unwrap_err();
+    let guard_2 = ReadGuard::try_upgrade(guard_2).unwrap_err();
+
+    drop(guard_1);
+
+    assert!(lock.try_write().is_none());
+    assert!(lock.try_read().is_some());
+    let guard_2 = ReadGuard::try_upgrade(guard_2).unwrap();
+    assert!(lock.try_read().is_none());
+    let guard_2 = WriteGuard::downgrade(guard_2);
+    assert!(lock.try_read().is_some());
+
+    drop(guard_2);
+
+    assert!(!lock.is_locked());
+    assert!(!lock.is_write_locked());
+}
+
+#[test]
+fn test_upgrade_spin() {
+    let lock = TryRwLock::new(1);
+
+    let guard_1 = lock.try_read().unwrap();
+    let guard_2 = lock.try_read().unwrap();
+    let guard_1 = ReadGuard::upgrade_spin(guard_1, 10).unwrap_err();
+    assert_eq!(lock.reader_count(), 2);
+
+    std::thread::scope(|s| {
+        s.spawn(|| {
+            std::thread::sleep(std::time::Duration::from_millis(10));
+            drop(guard_2);
+        });
+        let mut guard = ReadGuard::upgrade_spin(guard_1, usize::MAX).unwrap();
+        *guard += 1;
+    });
+    assert!(!lock.is_locked());
+    assert_eq!(*lock.try_read().unwrap(), 2);
+}
+
+#[test]
+fn test_spin_read_write() {
+    let lock = TryRwLock::new(1);
+    assert_eq!(*lock.spin_read(0).unwrap(), 1);
+    *lock.spin_write(0).unwrap() += 1;
+
+    let writer = lock.try_write().unwrap();
+    assert!(lock.spin_read(10).is_none());
+    assert!(lock.spin_write(10).is_none());
+    std::thread::scope(|s| {
+        s.spawn(|| {
+            std::thread::sleep(std::time::Duration::from_millis(10));
+            drop(writer);
+        });
+        assert_eq!(*lock.spin_read(usize::MAX).unwrap(), 2);
+    });
+
+    let reader = lock.try_read().unwrap();
+    assert!(lock.spin_write(10).is_none());
+    std::thread::scope(|s| {
+        s.spawn(|| {
+            std::thread::sleep(std::time::Duration::from_millis(10));
+            drop(reader);
+        });
+        *lock.spin_write(usize::MAX).unwrap() += 1;
+    });
+    assert!(!lock.is_locked());
+    assert_eq!(*lock.try_read().unwrap(), 3);
+}
+
+#[test]
+fn test_try_read_weak() {
+    let lock = TryRwLock::new(1);
+    let first = loop {
+        if let Some(guard) = lock.try_read_weak() {
+            break guard;
+        }
+    };
+    let second = loop {
+        if let Some(guard) = lock.try_read_weak() {
+            break guard;
+        }
+    };
+    assert_eq!(*first + *second, 2);
+    assert_eq!(lock.reader_count(), 2);
+    drop((first, second));
+
+    let writer = lock.try_write().unwrap();
+    assert!(lock.try_read_weak().is_none());
+    drop(writer);
+
+    let lock = TryRwLock::<_, 1>::with_max_readers(1);
+    let _reader = lock.try_read().unwrap();
+    assert!(lock.try_read_weak().is_none());
+}
+
+#[test]
+fn test_read_map() {
+    let lock = TryRwLock::new(vec![1u8, 2, 3]);
+
+    let guard_1 = ReadGuard::map(lock.try_read().unwrap(), |v| &v[0]);
+    let guard_2 = ReadGuard::map(lock.try_read().unwrap(), |v| &v[1]);
+    let guard_3 = ReadGuard::map(lock.try_read().unwrap(), |v| &v[2]);
+
+    assert!(lock.is_locked());
+    assert!(!lock.is_write_locked());
+    assert_eq!(lock.state.load(atomic::Ordering::Relaxed), 3);
+
+    assert_eq!(*guard_1, 1);
+    assert_eq!(*guard_2, 2);
+    assert_eq!(*guard_3, 3);
+
+    let guard_1 = ReadGuard::unmap(guard_1);
+    assert_eq!(*guard_1, [1, 2, 3]);
+
+    drop(guard_1);
+    drop(guard_2);
+    drop(guard_3);
+
+    assert!(!lock.is_locked());
+    assert!(!lock.is_write_locked());
+    assert_eq!(lock.state.load(atomic::Ordering::Relaxed), 0);
+}
+
+#[test]
+fn test_read_map_field() {
+    struct Config {
+        name: String,
+        verbose: bool,
+    }
+    let lock = TryRwLock::new(Config {
+        name: "foo".to_owned(),
+        verbose: true,
+    });
+
+    let name = ReadGuard::map(lock.try_read().unwrap(), |config| &config.name);
+    assert_eq!(&*name, "foo");
+    assert!(lock.try_read().unwrap().verbose);
+    assert!(lock.try_write().is_none());
+
+    drop(name);
+    assert!(!lock.is_locked());
+    assert!(lock.try_write().is_some());
+}
+
+#[test]
+fn test_read_try_map() {
+    let lock = TryRwLock::new(Some(5));
+
+    let guard = ReadGuard::try_map(lock.try_read().unwrap(), Option::as_ref).unwrap();
+    assert_eq!(*guard, 5);
+    assert_eq!(lock.reader_count(), 1);
+    drop(guard);
+
+    *lock.try_write().unwrap() = None;
+
+    let guard = ReadGuard::try_map(lock.try_read().unwrap(), Option::as_ref).unwrap_err();
+    assert_eq!(*guard, None);
+    assert_eq!(lock.reader_count(), 1);
+    drop(guard);
+    assert!(!lock.is_locked());
+}
+
+#[test]
+fn test_clone_guard() {
+    let lock = TryRwLock::new(vec![1, 2, 3]);
+    let guard_1 = ReadGuard::map(lock.try_read().unwrap(), |v| &v[1]);
+    let guard_2 = ReadGuard::clone_guard(&guard_1).unwrap();
+    assert_eq!(lock.reader_count(), 2);
+
+    drop(guard_1);
+    assert_eq!(*guard_2, 2);
+    assert_eq!(lock.reader_count(), 1);
+    assert!(lock.try_write().is_none());
+    drop(guard_2);
+    assert!(!lock.is_locked());
+
+    lock.state
+        .store(DEFAULT_MAX_READERS - 1, atomic::Ordering::Relaxed);
+    let guard = lock.try_read().unwrap();
+    assert!(ReadGuard::clone_guard(&guard).is_none());
+    assert_eq!(lock.reader_count(), DEFAULT_MAX_READERS);
+}
+
+#[test]
+fn test_ptr_eq() {
+    let lock_1 = TryRwLock::new((1, 2));
+    let lock_2 = TryRwLock::new((1, 2));
+    assert!(TryRwLock::ref_eq(&lock_1, &lock_1));
+    assert!(!TryRwLock::ref_eq(&lock_1, &lock_2));
+
+    let guard_1 = lock_1.try_read().unwrap();
+    let guard_2 = ReadGuard::map(lock_1.try_read().unwrap(), |(_, b)| b);
+    let guard_3 = lock_2.try_read().unwrap();
+    assert!(ReadGuard::ptr_eq(&guard_1, &guard_2));
+    assert!(!ReadGuard::ptr_eq(&guard_1, &guard_3));
+    assert!(!ReadGuard::ptr_eq(&guard_2, &guard_3));
+}
+
+#[test]
+fn test_as_ptr() {
+    let lock = TryRwLock::new([1, 2]);
+
+    let mut guard = lock.try_write().unwrap();
+    let ptr = WriteGuard::as_mut_ptr(&mut guard);
+    assert_eq!(ptr, lock.data_ptr());
+    unsafe { (*ptr)[0] = 3 };
+    let guard = WriteGuard::downgrade(guard);
+    assert_eq!(ReadGuard::as_ptr(&guard), ptr.cast_const());
+
+    let guard = ReadGuard::map(guard, |a| a);
+    assert_eq!(ReadGuard::as_ptr(&guard), ptr.cast_const());
+    let guard = ReadGuard::map(guard, |a| &a[1]);
+    assert_eq!(
+        ReadGuard::as_ptr(&guard),
+        ptr.cast::<i32>().wrapping_add(1).cast_const()
+    );
+    assert_eq!(lock.try_read().unwrap()[0], 3);
+}
+
+#[test]
+fn test_rwlock() {
+    let lock = TryRwLock::new(0);
+    let other = TryRwLock::new(0);
+
+    let reader = lock.try_read().unwrap();
+    let reader_lock = ReadGuard::rwlock(&reader);
+    assert!(TryRwLock::ref_eq(reader_lock, &lock));
+    assert!(!TryRwLock::ref_eq(reader_lock, &other));
+
+    let writer = ReadGuard::try_upgrade(reader).unwrap();
+    assert!(TryRwLock::ref_eq(WriteGuard::rwlock(&writer), reader_lock));
+
+    let upgradable = WriteGuard::downgrade_to_upgradable(writer);
+    assert!(TryRwLock::ref_eq(
+        UpgradableReadGuard::rwlock(&upgradable),
+        &lock
+    ));
+}
+
+#[test]
+fn test_reader_count() {
+    let lock = TryRwLock::new(());
+    assert_eq!(lock.reader_count(), 0);
+
+    let guard_1 = lock.try_read().unwrap();
+    assert_eq!(lock.reader_count(), 1);
+    let guard_2 = lock.try_read().unwrap();
+    assert_eq!(lock.reader_count(), 2);
+
+    drop(guard_1);
+    assert_eq!(lock.reader_count(), 1);
+    drop(guard_2);
+    assert_eq!(lock.reader_count(), 0);
+
+    let guard = lock.try_write().unwrap();
+    assert_eq!(lock.reader_count(), 0);
+    let guard = WriteGuard::downgrade(guard);
+    assert_eq!(lock.reader_count(), 1);
+    drop(guard);
+    assert_eq!(lock.reader_count(), 0);
+}
+
+#[test]
+fn test_bump() {
+    let lock = TryRwLock::new(0);
+    let mut guard = ReadGuard::bump(lock.try_read().unwrap()).unwrap();
+    assert_eq!(lock.reader_count(), 1);
+
+    std::thread::scope(|s| {
+        s.spawn(|| *lock.spin_write(usize::MAX).unwrap() = 1);
+        // The writer can only get in while the guard is being bumped.
+        while let Some(bumped) = ReadGuard::bump(guard) {
+            if *bumped == 1 {
+                break;
+            }
+            guard = bumped;
+        }
+    });
+    assert_eq!(*lock.try_read().unwrap(), 1);
+}
+
+#[test]
+fn test_into_from_raw() {
+    let lock = TryRwLock::new(1);
+
+    let ptr = ReadGuard::into_raw(lock.try_read().unwrap());
+    assert!(::core::ptr::eq(ptr, ::core::ptr::from_ref(&lock)));
+    assert_eq!(lock.reader_count(), 1);
+    let guard = unsafe { ReadGuard::from_raw(ptr) };
+    assert_eq!(*guard, 1);
+    drop(guard);
+    assert!(!lock.is_locked());
+
+    let ptr = WriteGuard::into_raw(lock.try_write().unwrap());
+    assert!(lock.is_write_locked());
+    let mut guard = unsafe { WriteGuard::from_raw(ptr) };
+    *guard += 1;
+    drop(guard);
+    assert!(!lock.is_locked());
+    assert_eq!(*lock.try_read().unwrap(), 2);
+}
+
+#[cfg(feature = "stats")]
+#[test]
+fn test_peak_readers() {
+    let lock = TryRwLock::new(0);
+    assert_eq!(lock.peak_readers(), 0);
+    drop(WriteGuard::downgrade(lock.try_write().unwrap()));
+    assert_eq!(lock.peak_readers(), 1);
+
+    let readers: Vec<_> = (0..5).map(|_| lock.try_read().unwrap()).collect();
+    assert_eq!(lock.peak_readers(), 5);
+    drop(readers);
+    assert_eq!(lock.peak_readers(), 5);
+
+    let upgradable = lock.try_upgradable_read().unwrap();
+    let readers: Vec<_> = (0..6).map(|_| lock.try_read().unwrap()).collect();
+    assert_eq!(lock.peak_readers(), 7);
+    drop((upgradable, readers));
+    assert!(!lock.is_locked());
+}
+
+#[test]
+fn test_reader_count_relaxed() {
+    let lock = TryRwLock::new(());
+    assert_eq!(lock.reader_count_relaxed(), 0);
+    let guard_1 = lock.try_read().unwrap();
+    let guard_2 = lock.try_upgradable_read().unwrap();
+    assert_eq!(lock.reader_count_relaxed(), 2);
+    drop((guard_1, guard_2));
+
+    let guard = lock.try_write().unwrap();
+    assert_eq!(lock.reader_count_relaxed(), 0);
+    drop(guard);
+
+    let lock = TryRwLock::new(0);
+    std::thread::scope(|s| {
+        for _ in 0..4 {
+            s.spawn(|| {
+                for _ in 0..1000 {
+                    let _guard = lock.try_read();
+                }
+            });
+        }
+        for _ in 0..1000 {
+            assert!(lock.reader_count_relaxed() <= 4);
+        }
+    });
+    assert_eq!(lock.reader_count_relaxed(), 0);
+}
+
+#[test]
+fn test_writer_count() {
+    let lock = TryRwLock::new(());
+    assert_eq!(lock.writer_count(), 0);
+
+    let guard = lock.try_read().unwrap();
+    assert_eq!(lock.writer_count(), 0);
+    drop(guard);
+
+    let guard = lock.try_write().unwrap();
+    assert_eq!(lock.writer_count(), 1);
+    drop(guard);
+
+    assert_eq!(lock.writer_count(), 0);
+}
+
+#[test]
+fn test_lock_state() {
+    let lock = TryRwLock::new(());
+    assert!(!lock.is_locked());
+    assert!(!lock.is_read_locked());
+    assert!(!lock.is_write_locked());
+
+    let guard_1 = lock.try_read().unwrap();
+    let guard_2 = lock.try_read().unwrap();
+    assert!(lock.is_locked());
+    assert!(lock.is_read_locked());
+    assert!(!lock.is_write_locked());
+    drop((guard_1, guard_2));
+
+    let guard = lock.try_write().unwrap();
+    assert!(lock.is_locked());
+    assert!(!lock.is_read_locked());
+    assert!(lock.is_write_locked());
+    drop(guard);
+
+    assert!(!lock.is_locked());
+    assert!(!lock.is_read_locked());
+    assert!(!lock.is_write_locked());
+}
+
+#[test]
+fn test_max_readers() {
+    let lock = TryRwLock::new(());
+    lock.state
+        .store(DEFAULT_MAX_READERS - 1, atomic::Ordering::Relaxed);
+
+    let guard = lock.try_read().unwrap();
+    assert_eq!(lock.reader_count(), (1 << (usize::BITS - 3)) - 1);
+    assert!(lock.is_read_locked());
+    assert!(!lock.is_write_locked());
+
+    assert!(lock.try_read().is_none());
+    assert!(lock.try_upgradable_read().is_none());
+    assert!(lock.try_write().is_none());
+    assert_eq!(lock.reader_count(), DEFAULT_MAX_READERS);
+    assert!(!lock.is_write_locked());
+
+    drop(guard);
+    assert_eq!(lock.reader_count(), DEFAULT_MAX_READERS - 1);
+    assert!(lock.try_read().is_some());
+}
+
+#[test]
+fn test_display() {
+    let lock = TryRwLock::new(5);
+    assert_eq!(lock.to_string(), "5");
+    assert_eq!(format!("{lock:>3}"), "  5");
+
+    let reader = lock.try_read().unwrap();
+    assert_eq!(lock.to_string(), "5");
+    drop(reader);
+
+    let _writer = lock.try_write().unwrap();
+    assert_eq!(lock.to_string(), "<locked>");
+}
+
+#[test]
+fn test_clone() {
+    let lock = TryRwLock::new(vec![1, 2, 3]);
+    let guard = lock.try_read().unwrap();
+
+    let clone = lock.clone();
+    assert!(!clone.is_locked());
+    assert_eq!(lock.reader_count(), 1);
+    drop(guard);
+
+    *lock.try_write().unwrap() = Vec::new();
+    assert_eq!(*clone.try_read().unwrap(), [1, 2, 3]);
+}
+
+#[test]
+#[should_panic = "cannot clone a write-locked TryRwLock"]
+fn test_clone_write_locked() {
+    let lock = TryRwLock::new(0);
+    let _guard = lock.try_write().unwrap();
+    let _ = lock.clone();
+}
+
+#[test]
+fn test_eq() {
+    let lock = TryRwLock::new(1);
+    assert_eq!(lock, TryRwLock::new(1));
+    assert_ne!(lock, TryRwLock::new(2));
+
+    let guard = lock.try_read().unwrap();
+    assert_eq!(lock, lock);
+    assert_eq!(lock.reader_count(), 1);
+    drop(guard);
+    assert!(!lock.is_locked());
+}
+
+#[test]
+#[should_panic = "cannot compare a write-locked TryRwLock"]
+fn test_eq_write_locked() {
+    let lock = TryRwLock::new(0);
+    let _guard = lock.try_write().unwrap();
+    let _ = TryRwLock::new(0) == lock;
+}
+
+#[test]
+fn test_borrow() {
+    let lock = TryRwLock::new(1);
+    *lock.borrow_mut() += 1;
+    let reader = lock.borrow();
+    assert_eq!(*lock.borrow(), 2);
+    assert_eq!(lock.try_borrow_mut().unwrap_err(), TryLockError::ReadLocked);
+    drop(reader);
+
+    let writer = lock.try_borrow_mut().unwrap();
+    assert_eq!(lock.try_borrow().unwrap_err(), TryLockError::WriteLocked);
+    drop(writer);
+}
+
+#[test]
+#[should_panic = "already write-locked"]
+fn test_borrow_write_locked() {
+    let lock = TryRwLock::new(0);
+    let _guard = lock.borrow_mut();
+    let _ = lock.borrow();
+}
+
+#[test]
+#[should_panic = "already locked"]
+fn test_borrow_mut_locked() {
+    let lock = TryRwLock::new(0);
+    let _guard = lock.borrow();
+    let _ = lock.borrow_mut();
+}
+
+#[test]
+#[allow(clippy::mutable_key_type)]
+fn test_hash() {
+    use std::collections::HashSet;
+
+    let set: HashSet<_> = [TryRwLock::new(1), TryRwLock::new(2)].into_iter().collect();
+    assert!(set.contains(&TryRwLock::new(1)));
+    assert!(!set.contains(&TryRwLock::new(3)));
+    assert!(set.iter().all(|lock| !lock.is_locked()));
+}
+
+#[test]
+#[should_panic = "cannot hash a write-locked TryRwLock"]
+fn test_hash_write_locked() {
+    use std::hash::BuildHasher;
+
+    let lock = TryRwLock::new(0);
+    let _guard = lock.try_write().unwrap();
+    std::collections::hash_map::RandomState::new().hash_one(&lock);
+}
+
+#[test]
+fn test_get_cloned() {
+    let lock = TryRwLock::new("foo".to_owned());
+    let guard = lock.try_read().unwrap();
+    assert_eq!(lock.get_cloned().as_deref(), Some("foo"));
+    drop(guard);
+
+    let guard = lock.try_write().unwrap();
+    assert_eq!(lock.get_cloned(), None);
+    drop(guard);
+
+    assert!(!lock.is_locked());
+}
+
+#[test]
+fn test_try_clone_into() {
+    let lock = TryRwLock::new(vec![1, 2, 3]);
+    let mut buffer = Vec::with_capacity(16);
+    let ptr = buffer.as_ptr();
+
+    assert!(lock.try_clone_into(&mut buffer));
+    assert_eq!(buffer, [1, 2, 3]);
+    assert_eq!(buffer.as_ptr(), ptr);
+    assert!(!lock.is_locked());
+
+    let writer = lock.try_write().unwrap();
+    buffer.clear();
+    assert!(!lock.try_clone_into(&mut buffer));
+    assert!(buffer.is_empty());
+    drop(writer);
+}
+
+#[test]
+fn test_get() {
+    let counter = TryRwLock::new(3_u8);
+    assert_eq!(counter.get(), Some(3));
+    counter.set(4).unwrap();
+    let reader = counter.try_read().unwrap();
+    assert_eq!(counter.get(), Some(4));
+    drop(reader);
+
+    let writer = counter.try_write().unwrap();
+    assert_eq!(counter.get(), None);
+    drop(writer);
+    assert!(!counter.is_locked());
+}
+
+#[test]
+fn test_load_store() {
+    let lock = TryRwLock::new(1_u64);
+    assert_eq!(lock.try_load(), Some(1));
+    assert!(lock.try_store(2));
+    assert_eq!(lock.try_load(), Some(2));
+    assert!(!lock.is_locked());
+
+    let reader = lock.try_read().unwrap();
+    assert_eq!(lock.try_load(), Some(2));
+    assert!(!lock.try_store(3));
+    drop(reader);
+
+    let writer = lock.try_write().unwrap();
+    assert_eq!(lock.try_load(), None);
+    assert!(!lock.try_store(3));
+    drop(writer);
+    assert_eq!(lock.try_load(), Some(2));
+}
+
+#[test]
+fn test_set() {
+    let lock = TryRwLock::new(1);
+    assert_eq!(lock.set(2), Ok(()));
+    assert!(!lock.is_locked());
+    assert_eq!(*lock.try_read().unwrap(), 2);
+
+    let guard = lock.try_read().unwrap();
+    assert_eq!(lock.set(3), Err(3));
+    drop(guard);
+    assert_eq!(*lock.try_read().unwrap(), 2);
+}
+
+#[test]
+fn test_replace() {
+    let lock = TryRwLock::new(1);
+    assert_eq!(lock.replace(2), Ok(1));
+    assert!(!lock.is_locked());
+    assert_eq!(*lock.try_read().unwrap(), 2);
+
+    let guard = lock.try_write().unwrap();
+    assert_eq!(lock.replace(3), Err(3));
+    drop(guard);
+    assert_eq!(*lock.try_read().unwrap(), 2);
+}
+
+#[test]
+fn test_take() {
+    let lock = TryRwLock::new(vec![1, 2, 3]);
+    assert_eq!(lock.take(), Some(vec![1, 2, 3]));
+    assert!(!lock.is_locked());
+    assert_eq!(*lock.try_read().unwrap(), Vec::<i32>::default());
 
-    fn deref(&self) -> &Self::Target {
-        unsafe { self.data.as_ref() }
-    }
+    let guard = lock.try_read().unwrap();
+    assert_eq!(lock.take(), None);
+    drop(guard);
 }
-impl<T, U> DerefMut for WriteGuard<'_, T, U> {
-    fn deref_mut(&mut self) -> &mut Self::Target {
-        unsafe { self.data.as_mut() }
-    }
+
+#[test]
+fn test_compare_exchange() {
+    let lock = TryRwLock::new((1, 2));
+    assert_eq!(lock.compare_exchange((1, 2), (3, 4)), Ok((1, 2)));
+    assert_eq!(lock.compare_exchange((1, 2), (5, 6)), Err(Some((3, 4))));
+    assert!(!lock.is_locked());
+
+    let guard = lock.try_read().unwrap();
+    assert_eq!(lock.compare_exchange((3, 4), (5, 6)), Err(None));
+    drop(guard);
+    assert_eq!(*lock.try_read().unwrap(), (3, 4));
 }
 
-impl<T, U> Drop for WriteGuard<'_, T, U> {
-    fn drop(&mut self) {
-        self.lock.readers.store(0, atomic::Ordering::Release);
-    }
+#[test]
+fn test_fetch_update() {
+    let lock = TryRwLock::new(vec![1]);
+    assert_eq!(
+        lock.fetch_update(|v| Some([v.as_slice(), &[2]].concat())),
+        Ok(vec![1])
+    );
+    assert_eq!(lock.fetch_update(|_| None), Err(Some(vec![1, 2])));
+    assert!(!lock.is_locked());
+
+    let guard = lock.try_read().unwrap();
+    assert_eq!(lock.fetch_update(|_| unreachable!()), Err(None));
+    drop(guard);
+    assert_eq!(*lock.try_read().unwrap(), [1, 2]);
 }
 
-impl<T, U: Debug> Debug for WriteGuard<'_, T, U> {
-    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
-        f.debug_struct("TryRwLockWriteGuard")
-            .field("data", &**self)
-            .finish()
-    }
+#[test]
+fn test_fetch_add_sub() {
+    let counter = TryRwLock::new(10_u64);
+    assert_eq!(counter.fetch_add(5), Some(10));
+    assert_eq!(counter.fetch_sub(3), Some(15));
+    assert_eq!(counter.get(), Some(12));
+    assert!(!counter.is_locked());
+
+    let reader = counter.try_read().unwrap();
+    assert_eq!(counter.fetch_add(1), None);
+    assert_eq!(counter.fetch_sub(1), None);
+    drop(reader);
+    assert_eq!(counter.get(), Some(12));
+
+    let float = TryRwLock::new(0.5_f64);
+    assert_eq!(float.fetch_add(0.25), Some(0.5));
+    assert_eq!(float.get(), Some(0.75));
 }
 
-impl<T, U: Display> Display for WriteGuard<'_, T, U> {
-    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
-        Display::fmt(&**self, f)
+#[test]
+fn test_swap() {
+    let front = TryRwLock::new(vec![1]);
+    let back = TryRwLock::new(vec![2, 3]);
+
+    assert!(front.swap(&back));
+    assert_eq!(*front.try_read().unwrap(), [2, 3]);
+    assert_eq!(*back.try_read().unwrap(), [1]);
+    assert!(!front.is_locked() && !back.is_locked());
+
+    for (locked, other) in [(&front, &back), (&back, &front)] {
+        let reader = locked.try_read().unwrap();
+        assert!(!front.swap(&back));
+        assert!(!other.is_locked());
+        assert_eq!(locked.reader_count(), 1);
+        drop(reader);
     }
+    assert_eq!(*front.try_read().unwrap(), [2, 3]);
+
+    assert!(front.swap(&front));
+    let reader = front.try_read().unwrap();
+    assert!(!front.swap(&front));
+    drop(reader);
+    assert_eq!(*front.try_read().unwrap(), [2, 3]);
 }
 
 #[test]
-fn test_read() {
-    let lock = TryRwLock::new("Hello World!".to_owned());
+fn test_update() {
+    let lock = TryRwLock::new(1);
+    assert_eq!(
+        lock.update(|n| {
+            *n += 1;
+            *n * 10
+        }),
+        Some(20)
+    );
     assert!(!lock.is_locked());
-    assert!(!lock.is_write_locked());
+    assert_eq!(*lock.try_read().unwrap(), 2);
 
-    let guard_1 = lock.try_read().unwrap();
-    let guard_2 = lock.try_read().unwrap();
+    let guard = lock.try_read().unwrap();
+    assert_eq!(lock.update(|_| unreachable!()), None::<()>);
+    drop(guard);
+}
 
-    assert_eq!(&*guard_1, "Hello World!");
-    assert_eq!(&*guard_2, "Hello World!");
+#[test]
+fn test_replace_with() {
+    let lock = TryRwLock::new(vec![1, 2]);
+    lock.replace_with(|v| v.into_iter().map(|n| n * 10).rev().collect())
+        .unwrap();
+    assert!(!lock.is_locked());
+    assert_eq!(*lock.try_read().unwrap(), [20, 10]);
 
-    assert!(lock.try_write().is_none());
-    assert!(lock.is_locked());
-    assert!(!lock.is_write_locked());
-    let guard_1 = ReadGuard::try_upgrade(guard_1).unwrap_err();
-    let guard_2 = ReadGuard::try_upgrade(guard_2).unwrap_err();
+    let guard = lock.try_read().unwrap();
+    assert_eq!(
+        lock.replace_with(|_| unreachable!()),
+        Err(TryLockError::ReadLocked)
+    );
+    drop(guard);
+    let guard = lock.try_write().unwrap();
+    assert_eq!(
+        lock.replace_with(|_| unreachable!()),
+        Err(TryLockError::WriteLocked)
+    );
+    drop(guard);
+}
 
-    drop(guard_1);
+#[test]
+fn test_read_map_or() {
+    let lock = TryRwLock::new(vec![1, 2, 3]);
+    assert_eq!(lock.read_map(Vec::len), Some(3));
+    assert_eq!(lock.read_map_or(0, Vec::len), 3);
+    assert_eq!(lock.read_map_or_else(|| unreachable!(), Vec::len), 3);
+    assert!(!lock.is_locked());
 
-    assert!(lock.try_write().is_none());
-    assert!(lock.try_read().is_some());
-    let guard_2 = ReadGuard::try_upgrade(guard_2).unwrap();
-    assert!(lock.try_read().is_none());
-    let guard_2 = WriteGuard::downgrade(guard_2);
-    assert!(lock.try_read().is_some());
+    let guard = lock.try_write().unwrap();
+    assert_eq!(lock.read_map(|_| unreachable!()), None::<usize>);
+    assert_eq!(lock.read_map_or(0, |_| unreachable!()), 0);
+    assert_eq!(lock.read_map_or_else(|| 5, |_| unreachable!()), 5);
+    drop(guard);
+}
 
-    drop(guard_2);
+#[test]
+fn test_or_else() {
+    let lock = TryRwLock::new(1);
+    assert_eq!(lock.read_or_else(|n| *n, || unreachable!()), 1);
+    assert_eq!(
+        lock.write_or_else(
+            |n| {
+                *n += 1;
+                *n
+            },
+            || unreachable!()
+        ),
+        2
+    );
+    assert!(!lock.is_locked());
 
+    let reader = lock.try_read().unwrap();
+    assert_eq!(lock.write_or_else(|_| unreachable!(), || 0), 0);
+    drop(reader);
+    let writer = lock.try_write().unwrap();
+    assert_eq!(lock.read_or_else(|_| unreachable!(), || 0), 0);
+    assert_eq!(lock.write_or_else(|_| unreachable!(), || 0), 0);
+    drop(writer);
+    assert_eq!(*lock.try_read().unwrap(), 2);
+}
+
+#[test]
+fn test_with_read_write() {
+    let lock = TryRwLock::new("foo".to_owned());
+    assert_eq!(lock.with_read(String::len), Some(3));
+    assert_eq!(
+        lock.with_write(|s| {
+            s.push_str("bar");
+            s.len()
+        }),
+        Some(6)
+    );
     assert!(!lock.is_locked());
-    assert!(!lock.is_write_locked());
+
+    let guard = lock.try_write().unwrap();
+    assert_eq!(lock.with_read(String::len), None);
+    assert_eq!(lock.with_write(|s| s.len()), None);
+    drop(guard);
+
+    let guard = lock.try_read().unwrap();
+    assert_eq!(lock.with_read(String::clone).as_deref(), Some("foobar"));
+    assert_eq!(lock.with_write(|s| s.len()), None);
+    drop(guard);
 }
 
 #[test]
-fn test_read_map() {
-    let lock = TryRwLock::new(vec![1u8, 2, 3]);
+fn test_max_readers_param() {
+    let lock = TryRwLock::<_, 2>::with_max_readers(0);
+
+    let reader = lock.try_read().unwrap();
+    let upgradable = lock.try_upgradable_read().unwrap();
+    assert_eq!(
+        lock.try_read_err().unwrap_err(),
+        TryLockError::ReaderSaturated
+    );
+    assert!(ReadGuard::clone_guard(&reader).is_none());
+    assert!(lock.try_write().is_none());
+    assert_eq!(lock.reader_count(), 2);
+
+    drop(upgradable);
+    let reader_2 = lock.try_read().unwrap();
+    assert!(lock.try_upgradable_read().is_none());
+    drop(reader);
+    drop(reader_2);
+
+    let mut writer = lock.try_write().unwrap();
+    *writer += 1;
+    drop(writer);
+    assert_eq!(*lock.try_read().unwrap(), 1);
+
+    let closed = TryRwLock::<_, 0>::with_max_readers(());
+    assert!(closed.try_read().is_none());
+    assert!(closed.try_write().is_some());
+}
 
-    let guard_1 = ReadGuard::map(lock.try_read().unwrap(), |v| &v[0]);
-    let guard_2 = ReadGuard::map(lock.try_read().unwrap(), |v| &v[1]);
-    let guard_3 = ReadGuard::map(lock.try_read().unwrap(), |v| &v[2]);
+#[test]
+fn test_max_readers_const() {
+    assert_eq!(TryRwLock::<()>::MAX_READERS, usize::MAX >> 3);
+    assert_eq!(TryRwLock::<[u8], 4>::MAX_READERS, 4);
+
+    let lock = TryRwLock::new(());
+    lock.state
+        .store(TryRwLock::<()>::MAX_READERS, atomic::Ordering::Relaxed);
+    assert_eq!(
+        lock.try_read_err().unwrap_err(),
+        TryLockError::ReaderSaturated
+    );
+}
 
-    assert!(lock.is_locked());
-    assert!(!lock.is_write_locked());
-    assert_eq!(lock.readers.load(atomic::Ordering::Relaxed), 3);
+#[test]
+fn test_backing_out_reader() {
+    let lock = TryRwLock::new(());
 
-    assert_eq!(*guard_1, 1);
-    assert_eq!(*guard_2, 2);
-    assert_eq!(*guard_3, 3);
+    let writer = lock.try_write().unwrap();
+    assert!(lock.try_read().is_none());
+    assert_eq!(lock.state.load(atomic::Ordering::Relaxed), WRITER);
+
+    // Simulate a reader that has incremented the state but not yet seen the writer.
+    lock.state.fetch_add(1, atomic::Ordering::Relaxed);
+    assert_eq!(lock.reader_count(), 0);
+    assert!(!lock.is_read_locked());
+
+    let reader = WriteGuard::downgrade(writer);
+    assert_eq!(lock.state.load(atomic::Ordering::Relaxed), 2);
+    lock.state.fetch_sub(1, atomic::Ordering::Relaxed);
+    drop(reader);
+    assert_eq!(lock.state.load(atomic::Ordering::Relaxed), 0);
+
+    let writer = lock.try_write().unwrap();
+    lock.state.fetch_add(1, atomic::Ordering::Relaxed);
+    drop(writer);
+    assert!(lock.try_write().is_none());
+    lock.state.fetch_sub(1, atomic::Ordering::Relaxed);
+    assert!(!lock.is_locked());
+}
 
-    let guard_1 = ReadGuard::unmap(guard_1);
-    assert_eq!(*guard_1, [1, 2, 3]);
+#[test]
+fn test_try_lock_error() {
+    let lock = TryRwLock::new(());
+
+    let reader = lock.try_read_err().unwrap();
+    assert_eq!(lock.try_write_err().unwrap_err(), TryLockError::ReadLocked);
+    drop(reader);
+
+    let writer = lock.try_write_err().unwrap();
+    assert_eq!(lock.try_read_err().unwrap_err(), TryLockError::WriteLocked);
+    assert_eq!(lock.try_write_err().unwrap_err(), TryLockError::WriteLocked);
+    drop(writer);
+
+    lock.state
+        .store(DEFAULT_MAX_READERS, atomic::Ordering::Relaxed);
+    assert_eq!(
+        lock.try_read_err().unwrap_err(),
+        TryLockError::ReaderSaturated
+    );
+    assert_eq!(lock.reader_count(), DEFAULT_MAX_READERS);
+    assert_eq!(
+        TryLockError::ReaderSaturated.to_string(),
+        "lock has the maximum number of readers"
+    );
+}
 
-    drop(guard_1);
-    drop(guard_2);
-    drop(guard_3);
+#[test]
+fn test_try_modify_then_read() {
+    let lock = TryRwLock::new(1);
+
+    let guard = lock
+        .try_modify_then_read(|n| {
+            assert!(lock.try_read().is_none());
+            assert!(lock.try_write().is_none());
+            *n += 1;
+        })
+        .unwrap();
+    assert_eq!(*guard, 2);
+    assert_eq!(lock.reader_count(), 1);
+    assert!(lock.try_write().is_none());
 
+    assert!(lock.try_modify_then_read(|_| unreachable!()).is_none());
+    drop(guard);
     assert!(!lock.is_locked());
-    assert!(!lock.is_write_locked());
-    assert_eq!(lock.readers.load(atomic::Ordering::Relaxed), 0);
 }
 
 #[test]
@@ -419,7 +3321,7 @@ fn test_write_map() {
 
     assert!(lock.is_locked());
     assert!(lock.is_write_locked());
-    assert_eq!(lock.readers.load(atomic::Ordering::Relaxed), usize::MAX);
+    assert_eq!(lock.state.load(atomic::Ordering::Relaxed), WRITER);
 
     assert_eq!(*guard, 1);
 
@@ -430,5 +3332,505 @@ fn test_write_map() {
 
     assert!(!lock.is_locked());
     assert!(!lock.is_write_locked());
-    assert_eq!(lock.readers.load(atomic::Ordering::Relaxed), 0);
+    assert_eq!(lock.state.load(atomic::Ordering::Relaxed), 0);
+}
+
+#[test]
+fn test_write_map_field() {
+    struct Point {
+        x: i32,
+        y: i32,
+    }
+    let lock = TryRwLock::new(Point { x: 1, y: 2 });
+
+    let mut x = WriteGuard::map(lock.try_write().unwrap(), |point| &mut point.x);
+    *x += 10;
+    assert_eq!(*x, 11);
+    assert!(lock.try_read().is_none());
+
+    drop(x);
+    assert!(!lock.is_locked());
+    let point = lock.try_read().unwrap();
+    assert_eq!((point.x, point.y), (11, 2));
+}
+
+#[test]
+fn test_write_try_map() {
+    #[derive(Debug)]
+    enum State {
+        Running(u32),
+        Stopped,
+    }
+    fn running(state: &mut State) -> Option<&mut u32> {
+        match state {
+            State::Running(ticks) => Some(ticks),
+            State::Stopped => None,
+        }
+    }
+
+    let lock = TryRwLock::new(State::Running(1));
+
+    let mut ticks = WriteGuard::try_map(lock.try_write().unwrap(), running).unwrap();
+    *ticks += 1;
+    assert!(lock.is_write_locked());
+    drop(ticks);
+    assert!(matches!(*lock.try_read().unwrap(), State::Running(2)));
+
+    *lock.try_write().unwrap() = State::Stopped;
+
+    let mut guard = WriteGuard::try_map(lock.try_write().unwrap(), running).unwrap_err();
+    assert!(lock.is_write_locked());
+    *guard = State::Running(0);
+    drop(guard);
+    assert!(!lock.is_locked());
+    assert!(matches!(*lock.try_read().unwrap(), State::Running(0)));
+}
+
+#[test]
+fn test_map_split() {
+    let lock = TryRwLock::new((1, String::from("a")));
+
+    let (mut number, string) = WriteGuard::map_split(lock.try_write().unwrap(), |(n, s)| (n, s));
+    let mut string = SplitWriteGuard::map(string, |s| s);
+    std::thread::scope(|s| {
+        s.spawn(move || string.push('b'));
+        *number += 1;
+        assert!(lock.is_write_locked());
+        assert!(lock.try_read().is_none());
+        assert!(lock.try_upgradable_read().is_none());
+    });
+
+    // One half has been dropped, but the other still holds the lock.
+    assert!(lock.try_read().is_none());
+    assert!(lock.try_write().is_none());
+    assert_eq!(*number, 2);
+    drop(number);
+
+    assert!(!lock.is_locked());
+    assert_eq!(*lock.try_read().unwrap(), (2, String::from("ab")));
+}
+
+#[test]
+fn test_map_chained() {
+    let lock = TryRwLock::new(vec![(1u8, [2u8, 3]), (4, [5, 6])]);
+
+    let guard = ReadGuard::map(lock.try_read().unwrap(), |v| &v[1]);
+    let guard = ReadGuard::map(guard, |(_, array)| &array[0]);
+    assert_eq!(*guard, 5);
+    assert_eq!(lock.reader_count(), 1);
+    drop(guard);
+
+    let guard = WriteGuard::map(lock.try_write().unwrap(), |v| &mut v[0]);
+    let mut guard = WriteGuard::map(guard, |(_, array)| &mut array[1]);
+    *guard = 7;
+    assert!(lock.is_write_locked());
+    drop(guard);
+
+    assert!(!lock.is_locked());
+    assert_eq!(lock.try_read().unwrap()[0], (1, [2, 7]));
+}
+
+#[test]
+fn test_upgradable_read() {
+    let lock = TryRwLock::new(1);
+
+    let upgradable = lock.try_upgradable_read().unwrap();
+    assert_eq!(*upgradable, 1);
+    assert!(lock.try_upgradable_read().is_none());
+    assert!(lock.try_write().is_none());
+
+    let reader = lock.try_read().unwrap();
+    assert_eq!(lock.reader_count(), 2);
+    let reader = ReadGuard::try_upgrade(reader).unwrap_err();
+    let upgradable = UpgradableReadGuard::try_upgrade(upgradable).unwrap_err();
+    drop(reader);
+
+    let mut writer = UpgradableReadGuard::try_upgrade(upgradable).unwrap();
+    assert!(lock.is_write_locked());
+    assert!(lock.try_read().is_none());
+    assert!(lock.try_upgradable_read().is_none());
+    *writer = 2;
+    drop(writer);
+    assert!(!lock.is_locked());
+
+    let upgradable = lock.try_upgradable_read().unwrap();
+    let reader = UpgradableReadGuard::downgrade(upgradable);
+    assert_eq!(*reader, 2);
+    assert_eq!(lock.reader_count(), 1);
+    let upgradable = lock.try_upgradable_read().unwrap();
+    drop(reader);
+    assert_eq!(lock.reader_count(), 1);
+    drop(upgradable);
+    assert!(!lock.is_locked());
+    assert_eq!(lock.state.load(atomic::Ordering::Relaxed), 0);
+}
+
+#[test]
+fn test_map_unsized() {
+    let lock = TryRwLock::new(vec![1_u8, 2, 3]);
+
+    let guard: ReadGuard<'_, Vec<u8>, [u8]> =
+        ReadGuard::map(lock.try_read().unwrap(), Vec::as_slice);
+    assert_eq!(&*guard, [1, 2, 3]);
+    let guard = ReadGuard::try_map(guard, |bytes| bytes.get(1..)).unwrap();
+    assert_eq!(&*guard, [2, 3]);
+    drop(guard);
+
+    let mut guard = WriteGuard::map(lock.try_write().unwrap(), Vec::as_mut_slice);
+    guard.reverse();
+    let reader = WriteGuard::downgrade_map(guard, |bytes| &bytes[..1]);
+    assert_eq!(&*reader, [3]);
+    drop(reader);
+
+    let guard = WriteGuard::map(lock.try_write().unwrap(), |v| v as &mut dyn Debug);
+    assert_eq!(
+        format!("{guard:?}"),
+        "TryRwLockWriteGuard { data: [3, 2, 1] }"
+    );
+    drop(guard);
+    assert!(!lock.is_locked());
+}
+
+#[test]
+fn test_downgrade_map() {
+    let lock = TryRwLock::new((0, "old"));
+
+    let mut writer = lock.try_write().unwrap();
+    writer.0 = 1;
+    writer.1 = "new";
+    let reader = WriteGuard::downgrade_map(writer, |(_, name)| name);
+    assert_eq!(*reader, "new");
+    assert_eq!(lock.reader_count(), 1);
+    assert!(lock.try_write().is_none());
+    assert_eq!(lock.try_read().unwrap().0, 1);
+    drop(reader);
+    assert!(!lock.is_locked());
+
+    let writer = WriteGuard::map(lock.try_write().unwrap(), |(n, _)| n);
+    let reader = WriteGuard::downgrade_map(writer, |n| n);
+    assert_eq!(*reader, 1);
+    drop(reader);
+    assert!(!lock.is_locked());
+}
+
+#[test]
+fn test_downgrade_to_upgradable() {
+    let lock = TryRwLock::new(1);
+
+    let writer = WriteGuard::map(lock.try_write().unwrap(), |n| n);
+    let upgradable = WriteGuard::downgrade_to_upgradable(writer);
+    assert!(!lock.is_write_locked());
+    assert!(lock.try_upgradable_read().is_none());
+    assert!(lock.try_write().is_none());
+
+    let reader = lock.try_read().unwrap();
+    assert_eq!(*reader, 1);
+    assert_eq!(lock.reader_count(), 2);
+    drop(reader);
+
+    let writer = UpgradableReadGuard::try_upgrade(upgradable).unwrap();
+    drop(writer);
+    assert!(!lock.is_locked());
+    assert!(lock.try_upgradable_read().is_some());
+}
+
+#[test]
+fn test_leak() {
+    let lock = TryRwLock::new(vec![1, 2, 3]);
+    let first = ReadGuard::leak(ReadGuard::map(lock.try_read().unwrap(), |v| &v[0]));
+    assert_eq!(*first, 1);
+    assert!(lock.try_write().is_none());
+    assert_eq!(*lock.try_read().unwrap(), [1, 2, 3]);
+    assert_eq!(lock.reader_count(), 1);
+
+    let lock = TryRwLock::new(1);
+    let data = WriteGuard::leak(lock.try_write().unwrap());
+    *data += 1;
+    assert_eq!(*data, 2);
+    assert!(lock.try_read().is_none());
+    assert!(lock.try_write().is_none());
+}
+
+#[cfg(feature = "cache-padded")]
+#[test]
+fn test_cache_padded() {
+    use ::core::mem::{align_of, size_of};
+
+    assert!(align_of::<TryRwLock<u8>>() >= 64);
+    assert!(size_of::<TryRwLock<u8>>() >= 64);
+
+    let locks = [TryRwLock::new(0u8), TryRwLock::new(1)];
+    let distance = ::core::ptr::from_ref(&locks[1].state) as usize
+        - ::core::ptr::from_ref(&locks[0].state) as usize;
+    assert!(distance >= 64);
+}
+
+#[test]
+fn test_state_transitions() {
+    // Walk through every transition between lock modes, which checks the debug assertions on the
+    // state along the way.
+    let lock = TryRwLock::new((0, 0));
+
+    let reader = lock.try_read().unwrap();
+    let other = lock.try_read().unwrap();
+    let reader = ReadGuard::try_upgrade(reader).unwrap_err();
+    drop(other);
+    let writer = ReadGuard::try_upgrade(reader).unwrap();
+    let upgradable = WriteGuard::downgrade_to_upgradable(writer);
+    let other = lock.try_read().unwrap();
+    let upgradable = UpgradableReadGuard::try_upgrade(upgradable).unwrap_err();
+    drop(other);
+    let writer = UpgradableReadGuard::try_upgrade(upgradable).unwrap();
+    let reader = WriteGuard::downgrade(writer);
+    drop(reader);
+
+    let upgradable = lock.try_upgradable_read().unwrap();
+    let reader = UpgradableReadGuard::downgrade(upgradable);
+    drop(reader);
+    drop(lock.try_upgradable_read().unwrap());
+
+    let (a, b) = WriteGuard::map_split(lock.try_write().unwrap(), |(a, b)| (a, b));
+    drop(b);
+    drop(a);
+    assert!(!lock.is_locked());
+}
+
+#[test]
+fn test_as_ref() {
+    fn sum(v: impl AsRef<[i32]>) -> i32 {
+        v.as_ref().iter().sum()
+    }
+    fn push(mut s: impl AsMut<String>) {
+        s.as_mut().push('!');
+    }
+
+    let lock: &TryRwLock<[i32]> = &TryRwLock::new([1, 2, 3]);
+    assert_eq!(sum(lock.try_read().unwrap()), 6);
+    assert_eq!(sum(lock.try_write().unwrap()), 6);
+    assert!(!lock.is_locked());
+
+    let lock = TryRwLock::new("Hello".to_owned());
+    push(lock.try_write().unwrap());
+    assert_eq!(AsRef::<String>::as_ref(&lock.try_read().unwrap()), "Hello!");
+}
+
+#[test]
+fn test_borrow_trait() {
+    fn max<B: Borrow<i32>>(values: &[B]) -> i32 {
+        values.iter().map(|v| *v.borrow()).max().unwrap()
+    }
+    fn double<B: BorrowMut<i32>>(mut value: B) {
+        *value.borrow_mut() *= 2;
+    }
+
+    let a = TryRwLock::new(1);
+    let b = TryRwLock::new(5);
+    assert_eq!(max(&[a.try_read().unwrap(), b.try_read().unwrap()]), 5);
+    assert_eq!(max(&[a.try_write().unwrap()]), 1);
+    double(a.try_write().unwrap());
+    assert_eq!(*a.try_read().unwrap(), 2);
+    assert!(!a.is_locked() && !b.is_locked());
+}
+
+#[test]
+fn test_try_from() {
+    fn lock_both<'a, G: TryFrom<&'a TryRwLock<i32>, Error = TryLockError>>(
+        a: &'a TryRwLock<i32>,
+        b: &'a TryRwLock<i32>,
+    ) -> Result<(G, G), TryLockError> {
+        Ok((G::try_from(a)?, G::try_from(b)?))
+    }
+
+    let a = TryRwLock::new(1);
+    let b = TryRwLock::new(2);
+    let (guard_a, guard_b) = lock_both::<ReadGuard<'_, i32>>(&a, &b).unwrap();
+    assert_eq!(*guard_a + *guard_b, 3);
+    assert_eq!(
+        lock_both::<WriteGuard<'_, i32>>(&a, &b).unwrap_err(),
+        TryLockError::ReadLocked
+    );
+    drop((guard_a, guard_b));
+
+    let writer = WriteGuard::try_from(&b).unwrap();
+    assert_eq!(
+        ReadGuard::try_from(&b).unwrap_err(),
+        TryLockError::WriteLocked
+    );
+    assert!(lock_both::<ReadGuard<'_, i32>>(&a, &b).is_err());
+    assert!(!a.is_locked());
+    drop(writer);
+}
+
+#[test]
+fn test_layout() {
+    use ::core::mem::{offset_of, size_of};
+
+    assert_eq!(offset_of!(TryRwLock<u16>, state), 0);
+    assert!(offset_of!(TryRwLock<u16>, data) >= size_of::<AtomicUsize>());
+    #[cfg(not(any(feature = "async", feature = "cache-padded", feature = "stats")))]
+    {
+        assert_eq!(size_of::<TryRwLock<()>>(), size_of::<usize>());
+        assert_eq!(offset_of!(TryRwLock<u16>, data), size_of::<usize>());
+        assert_eq!(
+            offset_of!(TryRwLock<u128>, data),
+            align_of::<u128>().max(size_of::<usize>())
+        );
+    }
+
+    let lock = TryRwLock::new(7_u16);
+    let state = ::core::ptr::from_ref(&lock).cast::<AtomicUsize>();
+    assert_eq!(unsafe { &*state }.load(atomic::Ordering::Relaxed), 0);
+    let _guard = lock.try_read().unwrap();
+    assert_eq!(unsafe { &*state }.load(atomic::Ordering::Relaxed), 1);
+}
+
+#[test]
+fn test_unsized() {
+    let lock: Box<TryRwLock<dyn Display>> = Box::new(TryRwLock::new(5));
+    assert_eq!(lock.try_read().unwrap().to_string(), "5");
+    assert!(lock.try_write().is_some());
+
+    let lock = TryRwLock::new([1, 2, 3]);
+    let slice: &TryRwLock<[i32]> = &lock;
+    let mut guard = slice.try_write().unwrap();
+    guard[0] = 4;
+    drop(guard);
+    assert_eq!(&*slice.try_read().unwrap(), [4, 2, 3]);
+    assert_eq!(format!("{slice:?}"), "TryRwLock { data: [4, 2, 3] }");
+}
+
+#[cfg(feature = "std")]
+#[test]
+fn test_blocking() {
+    use std::sync::atomic::AtomicBool;
+
+    let lock = TryRwLock::new(0);
+    let written = AtomicBool::new(false);
+
+    let reader = lock.read();
+    std::thread::scope(|s| {
+        s.spawn(|| {
+            *lock.write() += 1;
+            written.store(true, atomic::Ordering::Relaxed);
+        });
+        std::thread::sleep(std::time::Duration::from_millis(50));
+        assert!(!written.load(atomic::Ordering::Relaxed));
+        assert_eq!(*reader, 0);
+        drop(reader);
+    });
+
+    assert!(written.load(atomic::Ordering::Relaxed));
+    assert_eq!(*lock.read(), 1);
+}
+
+#[test]
+fn test_track_caller() {
+    use std::{
+        panic::{self, Location},
+        sync::{Arc, Mutex},
+    };
+
+    let location = Arc::new(Mutex::new(None));
+    let previous_hook: Arc<dyn Fn(&panic::PanicHookInfo<'_>) + Send + Sync> =
+        panic::take_hook().into();
+    panic::set_hook(Box::new({
+        let location = location.clone();
+        let previous_hook = previous_hook.clone();
+        let thread = std::thread::current().id();
+        move |info| {
+            if std::thread::current().id() == thread {
+                *location.lock().unwrap() = info.location().map(Location::line);
+            } else {
+                previous_hook(info);
+            }
+        }
+    }));
+
+    let lock = TryRwLock::new(0);
+    let _guard = lock.try_read().unwrap();
+    let expected_line = Location::caller().line() + 1;
+    let result = panic::catch_unwind(|| drop(lock.borrow_mut()));
+    panic::set_hook(Box::new(move |info| previous_hook(info)));
+
+    assert!(result.is_err());
+    assert_eq!(*location.lock().unwrap(), Some(expected_line));
+}
+
+#[test]
+fn test_unwind_safe() {
+    let lock = TryRwLock::new(0);
+    let result = std::panic::catch_unwind(|| {
+        let mut guard = lock.try_write().unwrap();
+        *guard = 1;
+        panic!("oh no");
+    });
+    assert!(result.is_err());
+    assert!(!lock.is_locked());
+    assert_eq!(*lock.try_read().unwrap(), 1);
+}
+
+#[test]
+fn test_panicking_drop() {
+    struct PanicOnDrop(bool);
+
+    impl Drop for PanicOnDrop {
+        fn drop(&mut self) {
+            assert!(!self.0, "dropped");
+        }
+    }
+
+    // The old value is dropped while the lock is write-locked, and unwinding then drops the guard.
+    let lock = TryRwLock::new(PanicOnDrop(true));
+    let result = std::panic::catch_unwind(|| lock.set(PanicOnDrop(false)).is_ok());
+    assert!(result.is_err());
+    assert!(!lock.is_locked());
+    assert!(!lock.try_read().unwrap().0);
+
+    // Guards never drop the data, so the value's destructor only runs once the lock is gone.
+    let lock = TryRwLock::new(PanicOnDrop(true));
+    drop(lock.try_write().unwrap());
+    assert!(!lock.is_locked());
+    let result = std::panic::catch_unwind(move || drop(lock.into_inner()));
+    assert!(result.is_err());
+}
+
+#[test]
+fn test_guards_send() {
+    fn assert_send<T: Send>() {}
+    assert_send::<ReadGuard<'_, u32>>();
+    assert_send::<WriteGuard<'_, u32>>();
+    assert_send::<UpgradableReadGuard<'_, u32>>();
+    assert_send::<ReadGuard<'_, (u32, u8), u8>>();
+
+    let lock = TryRwLock::new(1);
+    let mut guard = lock.try_write().unwrap();
+    std::thread::scope(|s| {
+        s.spawn(move || *guard += 1);
+    });
+    assert!(!lock.is_locked());
+    assert_eq!(*lock.try_read().unwrap(), 2);
+}
+
+#[test]
+fn test_try_upgrade_map() {
+    let lock = TryRwLock::new((1, "a".to_owned()));
+
+    let reader = lock.try_read().unwrap();
+    let other = lock.try_read().unwrap();
+    let reader =
+        ReadGuard::try_upgrade_map(reader, |_| -> &mut i32 { unreachable!() }).unwrap_err();
+    assert_eq!(reader.0, 1);
+    assert_eq!(lock.reader_count(), 2);
+    drop(other);
+
+    let reader = ReadGuard::map(reader, |(_, s)| s);
+    let mut writer = ReadGuard::try_upgrade_map(reader, |(n, _)| n).unwrap();
+    assert!(lock.is_write_locked());
+    *writer += 1;
+    let reader = WriteGuard::downgrade_map(writer, |n| n);
+    assert_eq!(*reader, 2);
+    drop(reader);
+
+    assert!(!lock.is_locked());
+    assert_eq!(*lock.try_read().unwrap(), (2, "a".to_owned()));
 }
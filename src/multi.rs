@@ -0,0 +1,150 @@
+//! Locking several locks at once, all-or-nothing.
+
+use crate::{ReadGuard, TryRwLock, WriteGuard};
+#[cfg(feature = "alloc")]
+use ::alloc::vec::Vec;
+
+/// Attempt to read-lock two locks together.
+///
+/// If the second lock cannot be locked, the guard of the first is dropped before returning `None`,
+/// so either both locks are locked or neither is.
+#[must_use]
+pub fn try_read2<
+    'a,
+    A: ?Sized,
+    B: ?Sized,
+    const A_MAX_READERS: usize,
+    const B_MAX_READERS: usize,
+>(
+    a: &'a TryRwLock<A, A_MAX_READERS>,
+    b: &'a TryRwLock<B, B_MAX_READERS>,
+) -> Option<(
+    ReadGuard<'a, A, A, A_MAX_READERS>,
+    ReadGuard<'a, B, B, B_MAX_READERS>,
+)> {
+    let a = a.try_read()?;
+    let b = b.try_read()?;
+    Some((a, b))
+}
+
+/// Attempt to write-lock two locks together.
+///
+/// Like [`try_read2`], this is all-or-nothing: if the second lock cannot be locked, the first is
+/// released again before returning `None`. Passing the same lock twice always fails.
+#[must_use]
+pub fn try_write2<
+    'a,
+    A: ?Sized,
+    B: ?Sized,
+    const A_MAX_READERS: usize,
+    const B_MAX_READERS: usize,
+>(
+    a: &'a TryRwLock<A, A_MAX_READERS>,
+    b: &'a TryRwLock<B, B_MAX_READERS>,
+) -> Option<(
+    WriteGuard<'a, A, A, A_MAX_READERS>,
+    WriteGuard<'a, B, B, B_MAX_READERS>,
+)> {
+    let a = a.try_write()?;
+    let b = b.try_write()?;
+    Some((a, b))
+}
+
+/// Attempt to read-lock every lock in a slice.
+///
+/// The locks are locked in order. If any of them cannot be locked, every guard acquired so far is
+/// dropped and this function returns `None`, so either all of the locks are locked or none of them
+/// are.
+///
+/// Requires the `alloc` feature.
+#[cfg(feature = "alloc")]
+#[must_use]
+pub fn try_read_all<T, const MAX_READERS: usize>(
+    locks: &[TryRwLock<T, MAX_READERS>],
+) -> Option<Vec<ReadGuard<'_, T, T, MAX_READERS>>> {
+    locks.iter().map(TryRwLock::try_read).collect()
+}
+
+/// Attempt to write-lock every lock in a slice.
+///
+/// Like [`try_read_all`], this is all-or-nothing: if any of the locks cannot be locked, the guards
+/// acquired so far are dropped and this function returns `None`.
+///
+/// Requires the `alloc` feature.
+#[cfg(feature = "alloc")]
+#[must_use]
+pub fn try_write_all<T, const MAX_READERS: usize>(
+    locks: &[TryRwLock<T, MAX_READERS>],
+) -> Option<Vec<WriteGuard<'_, T, T, MAX_READERS>>> {
+    locks.iter().map(TryRwLock::try_write).collect()
+}
+
+#[cfg(feature = "alloc")]
+#[test]
+fn test_try_read_all() {
+    let locks = [TryRwLock::new(1), TryRwLock::new(2), TryRwLock::new(3)];
+
+    let guards = try_read_all(&locks).unwrap();
+    assert_eq!(guards.iter().map(|guard| **guard).sum::<i32>(), 6);
+    assert!(try_write_all(&locks).is_none());
+    drop(guards);
+
+    let writer = locks[1].try_write().unwrap();
+    assert!(try_read_all(&locks).is_none());
+    assert!(!locks[0].is_locked());
+    assert!(!locks[2].is_locked());
+    drop(writer);
+}
+
+#[cfg(feature = "alloc")]
+#[test]
+fn test_try_write_all() {
+    let locks = [TryRwLock::new(1), TryRwLock::new(2), TryRwLock::new(3)];
+
+    let reader = locks[2].try_read().unwrap();
+    assert!(try_write_all(&locks).is_none());
+    assert!(!locks[0].is_locked());
+    assert!(!locks[1].is_locked());
+    drop(reader);
+
+    for mut guard in try_write_all(&locks).unwrap() {
+        *guard *= 10;
+    }
+    assert!(locks.iter().all(|lock| !lock.is_locked()));
+    assert_eq!(*locks[2].try_read().unwrap(), 30);
+}
+
+#[test]
+fn test_try_read2() {
+    let a = TryRwLock::new(1);
+    let b = TryRwLock::new("b");
+
+    let (guard_a, guard_b) = try_read2(&a, &b).unwrap();
+    assert_eq!((*guard_a, *guard_b), (1, "b"));
+    assert!(try_read2(&a, &a).is_some());
+    drop((guard_a, guard_b));
+
+    let writer = b.try_write().unwrap();
+    assert!(try_read2(&a, &b).is_none());
+    assert!(!a.is_locked());
+    drop(writer);
+}
+
+#[test]
+fn test_try_write2() {
+    let a = TryRwLock::new(1);
+    let b = TryRwLock::new(2);
+
+    let reader = b.try_read().unwrap();
+    assert!(try_write2(&a, &b).is_none());
+    assert!(!a.is_locked());
+    drop(reader);
+
+    assert!(try_write2(&a, &a).is_none());
+    assert!(!a.is_locked());
+
+    let (mut guard_a, mut guard_b) = try_write2(&a, &b).unwrap();
+    ::core::mem::swap(&mut *guard_a, &mut *guard_b);
+    drop((guard_a, guard_b));
+    assert_eq!((*a.try_read().unwrap(), *b.try_read().unwrap()), (2, 1));
+}
@@ -0,0 +1,195 @@
+//! Guards that keep their lock alive through an `Arc`.
+
+use crate::{TryRwLock, DEFAULT_MAX_READERS};
+use ::alloc::sync::Arc;
+use ::core::{
+    fmt::{self, Debug, Display, Formatter},
+    ops::{Deref, DerefMut},
+};
+
+impl<T: ?Sized, const MAX_READERS: usize> TryRwLock<T, MAX_READERS> {
+    /// Move this `TryRwLock` into an `Arc`, ready for [`try_read_owned`](Self::try_read_owned)
+    /// and [`try_write_owned`](Self::try_write_owned).
+    ///
+    /// Requires the `alloc` feature.
+    #[must_use]
+    pub fn into_arc(self) -> Arc<Self>
+    where
+        T: Sized,
+    {
+        Arc::new(self)
+    }
+
+    /// Attempt to lock this `TryRwLock` with shared read access, through an `Arc`.
+    ///
+    /// This behaves like [`try_read`](Self::try_read), but the returned guard owns a reference
+    /// count of the lock instead of borrowing it, so it is not tied to any lifetime.
+    ///
+    /// Requires the `alloc` feature.
+    #[must_use = "if unused the TryRwLock will immediately unlock"]
+    pub fn try_read_owned(self: Arc<Self>) -> Option<OwnedReadGuard<T, MAX_READERS>> {
+        self.lock_shared()
+            .ok()
+            .map(|()| OwnedReadGuard { lock: self })
+    }
+
+    /// Attempt to lock this `TryRwLock` with unique write access, through an `Arc`.
+    ///
+    /// This behaves like [`try_write`](Self::try_write), but the returned guard owns a reference
+    /// count of the lock instead of borrowing it, so it is not tied to any lifetime.
+    ///
+    /// Requires the `alloc` feature.
+    #[must_use = "if unused the TryRwLock will immediately unlock"]
+    pub fn try_write_owned(self: Arc<Self>) -> Option<OwnedWriteGuard<T, MAX_READERS>> {
+        self.lock_exclusive()
+            .ok()
+            .map(|()| OwnedWriteGuard { lock: self })
+    }
+}
+
+/// A RAII guard that guarantees shared read access to a `TryRwLock` held in an `Arc`.
+///
+/// Requires the `alloc` feature.
+#[must_use = "if unused the TryRwLock will immediately unlock"]
+pub struct OwnedReadGuard<T: ?Sized, const MAX_READERS: usize = DEFAULT_MAX_READERS> {
+    lock: Arc<TryRwLock<T, MAX_READERS>>,
+}
+
+impl<T: ?Sized, const MAX_READERS: usize> OwnedReadGuard<T, MAX_READERS> {
+    /// Get a shared reference to the lock that this read guard has locked.
+    #[must_use]
+    pub fn rwlock(guard: &Self) -> &Arc<TryRwLock<T, MAX_READERS>> {
+        &guard.lock
+    }
+}
+
+impl<T: ?Sized, const MAX_READERS: usize> Deref for OwnedReadGuard<T, MAX_READERS> {
+    type Target = T;
+
+    fn deref(&self) -> &Self::Target {
+        unsafe { &*self.lock.data.get() }
+    }
+}
+
+impl<T: ?Sized, const MAX_READERS: usize> Drop for OwnedReadGuard<T, MAX_READERS> {
+    fn drop(&mut self) {
+        unsafe { self.lock.unlock_shared() };
+    }
+}
+
+impl<T: ?Sized + Debug, const MAX_READERS: usize> Debug for OwnedReadGuard<T, MAX_READERS> {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        f.debug_struct("TryRwLockOwnedReadGuard")
+            .field("data", &&**self)
+            .finish()
+    }
+}
+
+impl<T: ?Sized + Display, const MAX_READERS: usize> Display for OwnedReadGuard<T, MAX_READERS> {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        Display::fmt(&**self, f)
+    }
+}
+
+/// A RAII guard that guarantees unique write access to a `TryRwLock` held in an `Arc`.
+///
+/// Requires the `alloc` feature.
+#[must_use = "if unused the TryRwLock will immediately unlock"]
+pub struct OwnedWriteGuard<T: ?Sized, const MAX_READERS: usize = DEFAULT_MAX_READERS> {
+    lock: Arc<TryRwLock<T, MAX_READERS>>,
+}
+
+impl<T: ?Sized, const MAX_READERS: usize> OwnedWriteGuard<T, MAX_READERS> {
+    /// Get a shared reference to the lock that this write guard has locked.
+    #[must_use]
+    pub fn rwlock(guard: &Self) -> &Arc<TryRwLock<T, MAX_READERS>> {
+        &guard.lock
+    }
+}
+
+impl<T: ?Sized, const MAX_READERS: usize> Deref for OwnedWriteGuard<T, MAX_READERS> {
+    type Target = T;
+
+    fn deref(&self) -> &Self::Target {
+        unsafe { &*self.lock.data.get() }
+    }
+}
+impl<T: ?Sized, const MAX_READERS: usize> DerefMut for OwnedWriteGuard<T, MAX_READERS> {
+    fn deref_mut(&mut self) -> &mut Self::Target {
+        unsafe { &mut *self.lock.data.get() }
+    }
+}
+
+impl<T: ?Sized, const MAX_READERS: usize> Drop for OwnedWriteGuard<T, MAX_READERS> {
+    fn drop(&mut self) {
+        unsafe { self.lock.unlock_exclusive() };
+    }
+}
+
+impl<T: ?Sized + Debug, const MAX_READERS: usize> Debug for OwnedWriteGuard<T, MAX_READERS> {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        f.debug_struct("TryRwLockOwnedWriteGuard")
+            .field("data", &&**self)
+            .finish()
+    }
+}
+
+impl<T: ?Sized + Display, const MAX_READERS: usize> Display for OwnedWriteGuard<T, MAX_READERS> {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        Display::fmt(&**self, f)
+    }
+}
+
+#[test]
+fn test_owned_read() {
+    let lock = Arc::new(TryRwLock::new("Hello World!".to_owned()));
+
+    let guard_1 = lock.clone().try_read_owned().unwrap();
+    let guard_2 = lock.clone().try_read_owned().unwrap();
+    assert_eq!(&*guard_1, "Hello World!");
+    assert_eq!(lock.reader_count(), 2);
+    assert!(lock.clone().try_write_owned().is_none());
+
+    let thread = std::thread::spawn(move || guard_2.len());
+    assert_eq!(thread.join().unwrap(), 12);
+    assert_eq!(lock.reader_count(), 1);
+
+    assert!(Arc::ptr_eq(OwnedReadGuard::rwlock(&guard_1), &lock));
+    drop(guard_1);
+    assert!(!lock.is_locked());
+}
+
+#[test]
+fn test_owned_write() {
+    let lock = Arc::new(TryRwLock::new(1));
+
+    let mut guard = lock.clone().try_write_owned().unwrap();
+    assert!(lock.is_write_locked());
+    assert!(lock.clone().try_read_owned().is_none());
+    // The guard keeps the lock alive on its own.
+    drop(lock);
+
+    *guard += 1;
+    let lock = OwnedWriteGuard::rwlock(&guard).clone();
+    let thread = std::thread::spawn(move || *guard += 1);
+    thread.join().unwrap();
+
+    assert!(!lock.is_locked());
+    assert_eq!(*lock.try_read().unwrap(), 3);
+}
+
+#[test]
+fn test_into_arc() {
+    let lock = TryRwLock::new(5).into_arc();
+    let guard = lock.clone().try_read_owned().unwrap();
+    assert_eq!(*guard, 5);
+    assert_eq!(Arc::strong_count(&lock), 2);
+    drop(guard);
+
+    let mut guard = lock.clone().try_write_owned().unwrap();
+    *guard += 1;
+    assert_eq!(Arc::strong_count(&lock), 2);
+    drop(guard);
+    assert_eq!(Arc::strong_count(&lock), 1);
+    assert_eq!(*lock.try_read().unwrap(), 6);
+}
@@ -0,0 +1,546 @@
+//! Asynchronous locking, implemented by registering wakers that are woken when a lock is released.
+
+use crate::{Backoff, ReadGuard, TryRwLock, WriteGuard, DEFAULT_MAX_READERS};
+use ::alloc::vec::Vec;
+use ::core::{
+    cell::UnsafeCell,
+    fmt::{self, Debug, Formatter},
+    future::Future,
+    mem,
+    pin::Pin,
+    sync::atomic::{self, AtomicBool},
+    task::{Context, Poll, Waker},
+};
+
+/// A list of wakers waiting for a lock to be released.
+pub(crate) struct WakerList {
+    /// Whether there are any wakers in the list, so that releasing a lock can check for waiters
+    /// without taking the spin lock.
+    waiting: AtomicBool,
+    /// A spin lock protecting `wakers`.
+    locked: AtomicBool,
+    wakers: UnsafeCell<Vec<Waker>>,
+}
+
+impl WakerList {
+    pub(crate) const fn new() -> Self {
+        Self {
+            waiting: AtomicBool::new(false),
+            locked: AtomicBool::new(false),
+            wakers: UnsafeCell::new(Vec::new()),
+        }
+    }
+
+    /// Run `f` with exclusive access to the list of wakers.
+    fn with<R>(&self, f: impl FnOnce(&mut Vec<Waker>) -> R) -> R {
+        let mut backoff = Backoff::new();
+        while self
+            .locked
+            .compare_exchange_weak(
+                false,
+                true,
+                atomic::Ordering::Acquire,
+                atomic::Ordering::Relaxed,
+            )
+            .is_err()
+        {
+            backoff.spin();
+        }
+        let res = f(unsafe { &mut *self.wakers.get() });
+        self.locked.store(false, atomic::Ordering::Release);
+        res
+    }
+
+    /// Add a waker to the list, if an equivalent one is not already in it.
+    fn register(&self, waker: &Waker) {
+        self.with(|wakers| {
+            if !wakers.iter().any(|w| w.will_wake(waker)) {
+                wakers.push(waker.clone());
+            }
+            self.waiting.store(true, atomic::Ordering::Relaxed);
+        });
+        // Either the caller's next attempt at locking will see any release that happened before
+        // this fence, or the releaser will see `waiting` (see `TryRwLock::wake_readers`).
+        atomic::fence(atomic::Ordering::SeqCst);
+    }
+
+    /// Remove a waker equivalent to `waker` from the list, returning whether there was one.
+    fn unregister(&self, waker: &Waker) -> bool {
+        if !self.waiting.load(atomic::Ordering::Relaxed) {
+            return false;
+        }
+        self.with(|wakers| {
+            let Some(i) = wakers.iter().position(|w| w.will_wake(waker)) else {
+                return false;
+            };
+            wakers.remove(i);
+            self.waiting
+                .store(!wakers.is_empty(), atomic::Ordering::Relaxed);
+            true
+        })
+    }
+
+    /// Wake and remove the waker that has been in the list the longest.
+    pub(crate) fn wake_one(&self) {
+        if !self.waiting.load(atomic::Ordering::Relaxed) {
+            return;
+        }
+        let waker = self.with(|wakers| {
+            let waker = (!wakers.is_empty()).then(|| wakers.remove(0));
+            self.waiting
+                .store(!wakers.is_empty(), atomic::Ordering::Relaxed);
+            waker
+        });
+        if let Some(waker) = waker {
+            waker.wake();
+        }
+    }
+
+    /// Wake and remove every waker in the list.
+    pub(crate) fn wake_all(&self) {
+        if !self.waiting.load(atomic::Ordering::Relaxed) {
+            return;
+        }
+        let wakers = self.with(|wakers| {
+            self.waiting.store(false, atomic::Ordering::Relaxed);
+            mem::take(wakers)
+        });
+        for waker in wakers {
+            waker.wake();
+        }
+    }
+}
+
+impl Default for WakerList {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+/// The tasks waiting for a lock, kept apart by the kind of access they want so that releasing the
+/// lock only wakes the tasks that might now be able to acquire it.
+///
+/// All waiting readers are woken at once, since they can all get in together. Only one writer can,
+/// so writers are woken one at a time.
+#[derive(Default)]
+pub(crate) struct Waiters {
+    pub(crate) readers: WakerList,
+    pub(crate) writers: WakerList,
+}
+
+impl Waiters {
+    pub(crate) const fn new() -> Self {
+        Self {
+            readers: WakerList::new(),
+            writers: WakerList::new(),
+        }
+    }
+}
+
+impl<T: ?Sized, const MAX_READERS: usize> TryRwLock<T, MAX_READERS> {
+    /// Attempt to lock this `TryRwLock` with shared read access, or register the current task to
+    /// be woken when the lock is released.
+    ///
+    /// Requires the `async` feature.
+    pub fn poll_read(&self, cx: &mut Context<'_>) -> Poll<ReadGuard<'_, T, T, MAX_READERS>> {
+        poll_lock(&self.wakers.readers, cx, || self.try_read())
+    }
+
+    /// Attempt to lock this `TryRwLock` with unique write access, or register the current task to
+    /// be woken when the lock is released.
+    ///
+    /// Only one waiting writer is woken each time the lock is released, so a task that is woken
+    /// must poll again, or other writers may keep waiting until the lock is next released.
+    /// [`write_async`](Self::write_async) takes care of this when its future is dropped.
+    ///
+    /// Requires the `async` feature.
+    pub fn poll_write(&self, cx: &mut Context<'_>) -> Poll<WriteGuard<'_, T, T, MAX_READERS>> {
+        poll_lock(&self.wakers.writers, cx, || self.try_write())
+    }
+
+    /// Lock this `TryRwLock` with shared read access, waiting asynchronously until it can be
+    /// acquired.
+    ///
+    /// The lock is not fair, so if writers hold it continuously the future may never complete.
+    ///
+    /// Requires the `async` feature.
+    pub fn read_async(&self) -> ReadFuture<'_, T, MAX_READERS> {
+        ReadFuture { lock: self }
+    }
+
+    /// Lock this `TryRwLock` with unique write access, waiting asynchronously until it can be
+    /// acquired.
+    ///
+    /// The lock is not fair, so if readers continuously hold it the future may never complete.
+    ///
+    /// Requires the `async` feature.
+    pub fn write_async(&self) -> WriteFuture<'_, T, MAX_READERS> {
+        WriteFuture {
+            lock: self,
+            waker: None,
+        }
+    }
+}
+
+/// A future that locks a `TryRwLock` with shared read access, returned by
+/// [`TryRwLock::read_async`].
+///
+/// Requires the `async` feature.
+#[must_use = "futures do nothing unless polled"]
+pub struct ReadFuture<'lock, T: ?Sized, const MAX_READERS: usize = DEFAULT_MAX_READERS> {
+    lock: &'lock TryRwLock<T, MAX_READERS>,
+}
+
+impl<'lock, T: ?Sized, const MAX_READERS: usize> Future for ReadFuture<'lock, T, MAX_READERS> {
+    type Output = ReadGuard<'lock, T, T, MAX_READERS>;
+
+    fn poll(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Self::Output> {
+        self.lock.poll_read(cx)
+    }
+}
+
+impl<T: ?Sized + Debug, const MAX_READERS: usize> Debug for ReadFuture<'_, T, MAX_READERS> {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        f.debug_struct("TryRwLockReadFuture")
+            .field("lock", &self.lock)
+            .finish()
+    }
+}
+
+/// A future that locks a `TryRwLock` with unique write access, returned by
+/// [`TryRwLock::write_async`].
+///
+/// Requires the `async` feature.
+#[must_use = "futures do nothing unless polled"]
+pub struct WriteFuture<'lock, T: ?Sized, const MAX_READERS: usize = DEFAULT_MAX_READERS> {
+    lock: &'lock TryRwLock<T, MAX_READERS>,
+    /// The waker this future last registered, if it is waiting for the lock.
+    waker: Option<Waker>,
+}
+
+impl<'lock, T: ?Sized, const MAX_READERS: usize> Future for WriteFuture<'lock, T, MAX_READERS> {
+    type Output = WriteGuard<'lock, T, T, MAX_READERS>;
+
+    fn poll(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Self::Output> {
+        let this = self.get_mut();
+        let poll = this.lock.poll_write(cx);
+        match &poll {
+            Poll::Ready(_) => {
+                // Don't leave a stale waker behind to take the wakeup of another writer.
+                if let Some(waker) = this.waker.take() {
+                    this.lock.wakers.writers.unregister(&waker);
+                }
+            }
+            Poll::Pending => match &mut this.waker {
+                Some(waker) => waker.clone_from(cx.waker()),
+                None => this.waker = Some(cx.waker().clone()),
+            },
+        }
+        poll
+    }
+}
+
+impl<T: ?Sized, const MAX_READERS: usize> Drop for WriteFuture<'_, T, MAX_READERS> {
+    fn drop(&mut self) {
+        // If this future's waker was already taken from the list, it was woken in place of
+        // another writer, so pass the wakeup on.
+        if let Some(waker) = self.waker.take() {
+            if !self.lock.wakers.writers.unregister(&waker) {
+                self.lock.wake_writers();
+            }
+        }
+    }
+}
+
+impl<T: ?Sized + Debug, const MAX_READERS: usize> Debug for WriteFuture<'_, T, MAX_READERS> {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        f.debug_struct("TryRwLockWriteFuture")
+            .field("lock", &self.lock)
+            .finish()
+    }
+}
+
+fn poll_lock<G>(
+    wakers: &WakerList,
+    cx: &mut Context<'_>,
+    mut try_lock: impl FnMut() -> Option<G>,
+) -> Poll<G> {
+    if let Some(guard) = try_lock() {
+        return Poll::Ready(guard);
+    }
+    wakers.register(cx.waker());
+    // Try again in case the lock was released before the waker was registered.
+    try_lock().map_or(Poll::Pending, Poll::Ready)
+}
+
+#[cfg(test)]
+mod test_util {
+    use ::alloc::{sync::Arc, task::Wake};
+    use ::core::{
+        future::Future,
+        pin::pin,
+        sync::atomic::{self, AtomicUsize},
+        task::{Context, Poll, Waker},
+    };
+    use std::thread::{self, Thread};
+
+    /// A waker that counts how many times it has been woken.
+    #[derive(Default)]
+    pub(crate) struct CountingWaker(pub(crate) AtomicUsize);
+
+    impl CountingWaker {
+        pub(crate) fn new() -> (Arc<Self>, Waker) {
+            let this = Arc::new(Self::default());
+            let waker = Waker::from(this.clone());
+            (this, waker)
+        }
+
+        pub(crate) fn count(&self) -> usize {
+            self.0.load(atomic::Ordering::SeqCst)
+        }
+    }
+
+    impl Wake for CountingWaker {
+        fn wake(self: Arc<Self>) {
+            self.0.fetch_add(1, atomic::Ordering::SeqCst);
+        }
+    }
+
+    struct ThreadWaker(Thread);
+
+    impl Wake for ThreadWaker {
+        fn wake(self: Arc<Self>) {
+            self.0.unpark();
+        }
+    }
+
+    /// A minimal executor that runs a future to completion on the current thread.
+    pub(crate) fn block_on<F: Future>(future: F) -> F::Output {
+        let mut future = pin!(future);
+        let waker = Waker::from(Arc::new(ThreadWaker(thread::current())));
+        let mut cx = Context::from_waker(&waker);
+        loop {
+            match future.as_mut().poll(&mut cx) {
+                Poll::Ready(output) => break output,
+                Poll::Pending => thread::park(),
+            }
+        }
+    }
+}
+
+#[test]
+fn test_poll() {
+    let lock = TryRwLock::new(0);
+    let (counter, waker) = test_util::CountingWaker::new();
+    let mut cx = Context::from_waker(&waker);
+
+    let writer = lock.try_write().unwrap();
+    assert!(lock.poll_read(&mut cx).is_pending());
+    assert!(lock.poll_write(&mut cx).is_pending());
+    assert_eq!(counter.count(), 0);
+    drop(writer);
+    // The waker is woken once as a reader and once as a writer.
+    assert_eq!(counter.count(), 2);
+
+    let reader = match lock.poll_read(&mut cx) {
+        Poll::Ready(reader) => reader,
+        Poll::Pending => panic!("lock should be free"),
+    };
+    assert!(lock.poll_write(&mut cx).is_pending());
+    drop(reader);
+    assert_eq!(counter.count(), 3);
+    assert!(lock.poll_write(&mut cx).is_ready());
+}
+
+#[test]
+fn test_wake_kinds() {
+    let lock = TryRwLock::new(0);
+    let (read_counter, read_waker) = test_util::CountingWaker::new();
+    let (write_counter_1, write_waker_1) = test_util::CountingWaker::new();
+    let (write_counter_2, write_waker_2) = test_util::CountingWaker::new();
+    let write_counts = || (write_counter_1.count(), write_counter_2.count());
+    let wait_to_write = || {
+        for waker in [&write_waker_1, &write_waker_2] {
+            assert!(lock
+                .poll_write(&mut Context::from_waker(waker))
+                .is_pending());
+        }
+    };
+
+    // Dropping a reader only wakes a writer once it was the last one, and then only one.
+    let reader_1 = lock.try_read().unwrap();
+    let reader_2 = lock.try_read().unwrap();
+    wait_to_write();
+    drop(reader_1);
+    assert_eq!(write_counts(), (0, 0));
+    drop(reader_2);
+    assert_eq!(write_counts(), (1, 0));
+
+    // Dropping a writer wakes every reader, but only the next writer.
+    let writer = lock.try_write().unwrap();
+    assert!(lock
+        .poll_read(&mut Context::from_waker(&read_waker))
+        .is_pending());
+    wait_to_write();
+    drop(writer);
+    assert_eq!(read_counter.count(), 1);
+    assert_eq!(write_counts(), (1, 1));
+
+    // Downgrading only wakes readers.
+    let writer = lock.try_write().unwrap();
+    assert!(lock
+        .poll_read(&mut Context::from_waker(&read_waker))
+        .is_pending());
+    wait_to_write();
+    let reader = WriteGuard::downgrade(writer);
+    assert_eq!(read_counter.count(), 2);
+    assert_eq!(write_counts(), (1, 1));
+    drop(reader);
+    assert_eq!(write_counts(), (2, 1));
+}
+
+#[test]
+fn test_wake_writer_handoff() {
+    let lock = TryRwLock::new(0);
+    let (counter_1, waker_1) = test_util::CountingWaker::new();
+    let (counter_2, waker_2) = test_util::CountingWaker::new();
+
+    // A woken writer that loses the race waits for the winner to release the lock.
+    let writer = lock.try_write().unwrap();
+    assert!(lock
+        .poll_write(&mut Context::from_waker(&waker_1))
+        .is_pending());
+    assert!(lock
+        .poll_write(&mut Context::from_waker(&waker_2))
+        .is_pending());
+    drop(writer);
+    assert_eq!((counter_1.count(), counter_2.count()), (1, 0));
+    let reader = lock.try_read().unwrap();
+    assert!(lock
+        .poll_write(&mut Context::from_waker(&waker_1))
+        .is_pending());
+    drop(reader);
+    assert_eq!((counter_1.count(), counter_2.count()), (1, 1));
+    // Releasing this write lock wakes the first writer again.
+    assert!(lock
+        .poll_write(&mut Context::from_waker(&waker_2))
+        .is_ready());
+    assert_eq!((counter_1.count(), counter_2.count()), (2, 1));
+
+    // A write future that is dropped after being woken passes the wakeup on.
+    let writer = lock.try_write().unwrap();
+    let mut future_1 = lock.write_async();
+    let mut future_2 = lock.write_async();
+    assert!(Pin::new(&mut future_1)
+        .poll(&mut Context::from_waker(&waker_1))
+        .is_pending());
+    assert!(Pin::new(&mut future_2)
+        .poll(&mut Context::from_waker(&waker_2))
+        .is_pending());
+    drop(writer);
+    assert_eq!((counter_1.count(), counter_2.count()), (3, 1));
+    drop(future_1);
+    assert_eq!((counter_1.count(), counter_2.count()), (3, 2));
+    assert!(Pin::new(&mut future_2)
+        .poll(&mut Context::from_waker(&waker_2))
+        .is_ready());
+
+    // A write future that is dropped before being woken gives up its place instead.
+    let writer = lock.try_write().unwrap();
+    let mut future_1 = lock.write_async();
+    assert!(Pin::new(&mut future_1)
+        .poll(&mut Context::from_waker(&waker_1))
+        .is_pending());
+    assert!(lock
+        .poll_write(&mut Context::from_waker(&waker_2))
+        .is_pending());
+    drop(future_1);
+    drop(writer);
+    assert_eq!((counter_1.count(), counter_2.count()), (3, 3));
+}
+
+#[test]
+fn test_wake_saturated() {
+    let lock = TryRwLock::<_, 2>::with_max_readers(0);
+    let (counter, waker) = test_util::CountingWaker::new();
+    let mut cx = Context::from_waker(&waker);
+
+    let reader_1 = lock.try_read().unwrap();
+    let reader_2 = lock.try_read().unwrap();
+    for _ in 0..5 {
+        assert!(lock.poll_read(&mut cx).is_pending());
+    }
+    assert_eq!(counter.count(), 0);
+    drop(reader_1);
+    assert_eq!(counter.count(), 1);
+    assert!(lock.poll_read(&mut cx).is_ready());
+    drop(reader_2);
+
+    // With a single reader allowed, a write lock also looks full to a backing-out reader.
+    let lock = TryRwLock::<_, 1>::with_max_readers(0);
+    let (counter, waker) = test_util::CountingWaker::new();
+    let mut cx = Context::from_waker(&waker);
+    let writer = lock.try_write().unwrap();
+    for _ in 0..5 {
+        assert!(lock.poll_read(&mut cx).is_pending());
+    }
+    assert_eq!(counter.count(), 0);
+    drop(writer);
+    assert_eq!(counter.count(), 1);
+}
+
+#[test]
+fn test_async() {
+    let lock = TryRwLock::new(0);
+
+    let writer = lock.try_write().unwrap();
+    std::thread::scope(|s| {
+        s.spawn(|| {
+            let mut writer = writer;
+            std::thread::sleep(std::time::Duration::from_millis(50));
+            *writer = 1;
+        });
+        assert_eq!(*test_util::block_on(lock.read_async()), 1);
+    });
+
+    let reader = lock.try_read().unwrap();
+    std::thread::scope(|s| {
+        s.spawn(|| {
+            std::thread::sleep(std::time::Duration::from_millis(50));
+            drop(reader);
+        });
+        *test_util::block_on(lock.write_async()) += 1;
+    });
+    assert_eq!(*lock.try_read().unwrap(), 2);
+}
+
+#[test]
+fn test_futures() {
+    struct Pending<'lock> {
+        read: ReadFuture<'lock, i32>,
+        write: WriteFuture<'lock, i32>,
+    }
+
+    let lock = TryRwLock::new(0);
+    let (counter, waker) = test_util::CountingWaker::new();
+    let mut cx = Context::from_waker(&waker);
+
+    let writer = lock.try_write().unwrap();
+    let mut pending = Pending {
+        read: lock.read_async(),
+        write: lock.write_async(),
+    };
+    assert!(Pin::new(&mut pending.read).poll(&mut cx).is_pending());
+    assert!(Pin::new(&mut pending.write).poll(&mut cx).is_pending());
+    drop(writer);
+    assert_eq!(counter.count(), 2);
+
+    let reader = match Pin::new(&mut pending.read).poll(&mut cx) {
+        Poll::Ready(reader) => reader,
+        Poll::Pending => panic!("lock should be free"),
+    };
+    assert!(Pin::new(&mut pending.write).poll(&mut cx).is_pending());
+    drop(reader);
+    *test_util::block_on(pending.write) += 1;
+    assert_eq!(*test_util::block_on(lock.read_async()), 1);
+}